@@ -0,0 +1,61 @@
+use std::str::FromStr;
+
+use cosmwasm_std::{Coin, StdError, StdResult, Uint128};
+
+// merge a coin into a list, keeping denoms unique and sorted as bank messages expect
+pub fn add_coin(coins: &mut Vec<Coin>, coin: Coin) {
+    if coin.amount.is_zero() {
+        return;
+    }
+    if let Some(existing) = coins.iter_mut().find(|c| c.denom == coin.denom) {
+        existing.amount += coin.amount;
+    } else {
+        coins.push(coin);
+        coins.sort_by(|a, b| a.denom.cmp(&b.denom));
+    }
+}
+
+// "100uosmo,5uion", the format of the fee and refund attributes
+pub fn coins_to_string(coins: &[Coin]) -> String {
+    coins.iter().map(|coin| coin.to_string()).collect::<Vec<String>>().join(",")
+}
+
+pub fn parse_coins(value: &str) -> StdResult<Vec<Coin>> {
+    value
+        .split(',')
+        .filter(|item| !item.is_empty())
+        .map(|item| {
+            let denom_start = item
+                .find(|c: char| !c.is_ascii_digit())
+                .ok_or_else(|| StdError::generic_err(format!("Missing denom in {}", item)))?;
+            let (amount, denom) = item.split_at(denom_start);
+            Ok(Coin { denom: denom.to_string(), amount: Uint128::from_str(amount)? })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::coin;
+
+    #[test]
+    fn add_coin_merges_and_sorts() {
+        let mut coins = vec![];
+        add_coin(&mut coins, coin(5, "uosmo"));
+        add_coin(&mut coins, coin(3, "uatom"));
+        add_coin(&mut coins, coin(2, "uosmo"));
+        add_coin(&mut coins, coin(0, "uion"));
+        assert_eq!(coins, vec![coin(3, "uatom"), coin(7, "uosmo")]);
+    }
+
+    #[test]
+    fn coins_string_round_trip() {
+        let coins = vec![coin(3, "gamm/pool/2"), coin(100, "ibc/27394FB092D2ECCD"), coin(7, "uosmo")];
+        assert_eq!(coins_to_string(&coins), "3gamm/pool/2,100ibc/27394FB092D2ECCD,7uosmo");
+        assert_eq!(parse_coins(&coins_to_string(&coins)).unwrap(), coins);
+        assert_eq!(parse_coins("").unwrap(), vec![]);
+        assert!(parse_coins("100").is_err());
+        assert!(parse_coins("uosmo").is_err());
+    }
+}
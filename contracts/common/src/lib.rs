@@ -1 +1,3 @@
 pub mod types;
+pub mod pending_ops;
+pub mod coins;
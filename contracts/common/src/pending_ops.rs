@@ -0,0 +1,140 @@
+use cosmwasm_schema::{cw_serde};
+use cosmwasm_std::{from_binary, to_binary, Binary, Order, StdResult, Storage, Timestamp};
+use cw_storage_plus::{Bound, Item, Map};
+use serde::{de::DeserializeOwned, Serialize};
+
+// the lowest bits of a reply id hold the reply kind, the remaining bits hold the operation id
+const REPLY_KIND_BITS: u64 = 8;
+pub const MAX_REPLY_KIND: u64 = (1 << REPLY_KIND_BITS) - 1;
+
+pub fn encode_reply_id(op_id: u64, kind: u64) -> u64 {
+    (op_id << REPLY_KIND_BITS) | (kind & MAX_REPLY_KIND)
+}
+
+// returns (op_id, kind), op_id 0 is used by replies without pending state
+pub fn decode_reply_id(reply_id: u64) -> (u64, u64) {
+    (reply_id >> REPLY_KIND_BITS, reply_id & MAX_REPLY_KIND)
+}
+
+#[cw_serde]
+pub struct PendingOp {
+    pub op_id: u64,
+    // reply kind of the latest dispatched submessage
+    pub kind: u64,
+    pub created_at: Timestamp,
+    pub updated_at: Timestamp,
+    pub data: Binary,
+}
+
+/*
+    In-flight state of multi-step flows, one entry per operation instead of a single slot per flow
+    Operation ids increase monotonically and are encoded into the reply ids of their submessages,
+    entries are removed once the flow finishes so anything left behind is a stuck operation
+*/
+pub struct PendingOps<'a> {
+    ops: Map<'a, u64, PendingOp>,
+    last_op_id: Item<'a, u64>,
+}
+
+impl<'a> PendingOps<'a> {
+    pub const fn new(ops_namespace: &'a str, last_op_id_namespace: &'a str) -> Self {
+        PendingOps {
+            ops: Map::new(ops_namespace),
+            last_op_id: Item::new(last_op_id_namespace),
+        }
+    }
+
+    // stores the data under a new operation id and returns the reply id of its first submessage
+    pub fn start<T: Serialize>(
+        &self, storage: &mut dyn Storage, time: Timestamp, kind: u64, data: &T,
+    ) -> StdResult<u64> {
+        let op_id = self.last_op_id.may_load(storage)?.unwrap_or_default() + 1;
+        self.last_op_id.save(storage, &op_id)?;
+        self.ops.save(storage, op_id, &PendingOp {
+            op_id,
+            kind,
+            created_at: time,
+            updated_at: time,
+            data: to_binary(data)?,
+        })?;
+        Ok(encode_reply_id(op_id, kind))
+    }
+
+    // stores the data for the next step of an operation and returns the reply id of its submessage
+    pub fn next<T: Serialize>(
+        &self, storage: &mut dyn Storage, op_id: u64, time: Timestamp, kind: u64, data: &T,
+    ) -> StdResult<u64> {
+        let mut op = self.ops.load(storage, op_id)?;
+        op.kind = kind;
+        op.updated_at = time;
+        op.data = to_binary(data)?;
+        self.ops.save(storage, op_id, &op)?;
+        Ok(encode_reply_id(op_id, kind))
+    }
+
+    pub fn load<T: DeserializeOwned>(&self, storage: &dyn Storage, op_id: u64) -> StdResult<T> {
+        let op = self.ops.load(storage, op_id)?;
+        from_binary(&op.data)
+    }
+
+    pub fn finish(&self, storage: &mut dyn Storage, op_id: u64) {
+        self.ops.remove(storage, op_id);
+    }
+
+    pub fn range(
+        &self, storage: &dyn Storage, start_after: Option<u64>, limit: u64,
+    ) -> StdResult<Vec<PendingOp>> {
+        self.ops
+            .range(storage, start_after.map(Bound::exclusive), None, Order::Ascending)
+            .take(limit as usize)
+            .map(|item| item.map(|(_, op)| op))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    const OPS: PendingOps = PendingOps::new("pending_ops", "last_op_id");
+
+    #[test]
+    fn reply_id_round_trip() {
+        for (op_id, kind) in [(0, 1), (1, 1), (7, MAX_REPLY_KIND), (u64::MAX >> REPLY_KIND_BITS, 3)] {
+            assert_eq!(decode_reply_id(encode_reply_id(op_id, kind)), (op_id, kind));
+        }
+        // stateless replies keep their plain kind as reply id
+        assert_eq!(encode_reply_id(0, 5), 5);
+    }
+
+    #[test]
+    fn op_ids_increase_after_finish() {
+        let mut storage = MockStorage::new();
+        let time = Timestamp::from_seconds(1);
+        let first = decode_reply_id(OPS.start(&mut storage, time, 1, &"first".to_string()).unwrap());
+        let second = decode_reply_id(OPS.start(&mut storage, time, 2, &"second".to_string()).unwrap());
+        assert_eq!(first, (1, 1));
+        assert_eq!(second, (2, 2));
+
+        OPS.finish(&mut storage, 2);
+        let third = decode_reply_id(OPS.start(&mut storage, time, 1, &"third".to_string()).unwrap());
+        assert_eq!(third, (3, 1));
+
+        let pending: Vec<u64> = OPS.range(&storage, None, 10).unwrap().iter().map(|op| op.op_id).collect();
+        assert_eq!(pending, vec![1, 3]);
+    }
+
+    #[test]
+    fn next_keeps_op_id() {
+        let mut storage = MockStorage::new();
+        let reply_id = OPS.start(&mut storage, Timestamp::from_seconds(1), 1, &1u64).unwrap();
+        let (op_id, _) = decode_reply_id(reply_id);
+        let next_reply_id = OPS.next(&mut storage, op_id, Timestamp::from_seconds(2), 4, &2u64).unwrap();
+        assert_eq!(decode_reply_id(next_reply_id), (op_id, 4));
+        assert_eq!(OPS.load::<u64>(&storage, op_id).unwrap(), 2);
+        let op = OPS.range(&storage, None, 1).unwrap().remove(0);
+        assert_eq!(op.created_at, Timestamp::from_seconds(1));
+        assert_eq!(op.updated_at, Timestamp::from_seconds(2));
+    }
+}
@@ -1,5 +1,8 @@
 use cosmwasm_schema::{cw_serde};
 
+pub const BPS_DENOMINATOR: u64 = 10_000;
+pub const LP_DENOM_PREFIX: &str = "gamm/pool/";
+
 #[cw_serde]
 pub struct RemoveLiquidityParams {
     pub pool_id: u64,
@@ -9,16 +12,49 @@ pub struct RemoveLiquidityParams {
 }
 
 #[cw_serde]
-pub struct SwapParams {
+pub struct SwapRoute {
     pub pool_id: u64,
-    pub denom_out: String,
-    pub amount_out_min: String,
+    pub token_out_denom: String,
+}
+
+// either a single pool swap (pool_id, denom_out) or an ordered multi-hop route
+#[cw_serde]
+pub struct SwapParams {
+    pub pool_id: Option<u64>,
+    pub denom_out: Option<String>,
+    pub routes: Option<Vec<SwapRoute>>,
+}
+
+impl SwapParams {
+    // routes take precedence over the single pool fields, empty when neither is set
+    pub fn get_routes(&self) -> Vec<SwapRoute> {
+        match (&self.routes, self.pool_id, &self.denom_out) {
+            (Some(routes), _, _) if !routes.is_empty() => routes.clone(),
+            (_, Some(pool_id), Some(denom_out)) => vec![SwapRoute {
+                pool_id,
+                token_out_denom: denom_out.clone(),
+            }],
+            _ => vec![],
+        }
+    }
+
+    pub fn get_denom_out(&self) -> Option<String> {
+        self.get_routes().last().map(|route| route.token_out_denom.clone())
+    }
 }
 
 #[cw_serde]
-pub struct AddLiquidityParams {
-    pub amount: String,
+pub struct FeeParams {
+    pub recipient: String,
+    pub fee_bps: u64,
+}
+
+// a reward leg is swapped into a pool asset first when swap is set, joined to the pool directly otherwise
+#[cw_serde]
+pub struct RewardParams {
+    // None restakes the whole wallet balance of denom, minus reserve
+    pub amount: Option<String>,
     pub denom: String,
-    pub pool_id: u64,
-    pub share_out_min_amount: String,
+    pub reserve: Option<String>,
+    pub swap: Option<SwapParams>,
 }
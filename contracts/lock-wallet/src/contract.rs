@@ -1,24 +1,38 @@
 use std::env;
+use std::str::FromStr;
 
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
     CosmosMsg, Binary, Deps, DepsMut, Env, MessageInfo, Reply, Response, Addr,
-    StdResult, to_binary, SubMsg, SubMsgResponse, SubMsgResult,
+    StdResult, to_binary, SubMsg, SubMsgResponse, SubMsgResult, Uint128, Order,
 };
 use cw2::{get_contract_version, set_contract_version};
 
 use semver::Version;
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
+use crate::msg::{
+    ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, LockResponse, ConfigResponse, FeeStats, RestakeStats,
+    UnbondingResponse,
+};
 use crate::helper::{
     get_lp_denom,
+    estimate_swap_amount_out_min,
+    estimate_join_share_out_min,
+    estimate_join_pool_shares,
+    get_reward_balance,
+    take_fee,
+    get_lock_amount_and_duration,
+    get_account_locks,
+    validate_swap_prices,
+    validate_join_prices,
 };
 use crate::osmosis_msg::{
     get_single_transfer_msg,
     get_transfer_msg,
     get_add_liquidity_msg,
+    get_join_pool_msg,
     get_swap_msg,
     get_remove_liquidity_msg,
     get_superfluid_lock_and_delegate_msg,
@@ -27,24 +41,39 @@ use crate::osmosis_msg::{
     get_lock_tokens_msg,
     get_unlock_msg,
 };
-use common::types::{RemoveLiquidityParams, SwapParams, AddLiquidityParams};
+use common::types::{RemoveLiquidityParams, RewardParams, FeeParams, SwapParams, BPS_DENOMINATOR};
+use common::pending_ops::{encode_reply_id, decode_reply_id, PendingOp};
+use common::coins::{add_coin, coins_to_string};
 
 use crate::state::{
     OWNER,
-    DEPOSIT_PARAMS_REPLY_STATE,
+    CONFIG,
+    PENDING_OPS,
     DepositParamsState,
     RestakeParamsState,
-    RECEIVER_REPLY_STATE,
-    RESTAKE_REPLY_STATE,
+    RestakeLegState,
+    LockParamsState,
+    LOCKS,
+    LockInfo,
+    UNBONDINGS,
+    UnbondingInfo,
+    LAST_UNBONDING_ID,
+    FEE_STATS,
+    RESTAKE_STATS,
 };
 
 const CONTRACT_NAME: &str = "crates.io:lock-wallet";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-const ADD_LIQUIDITY_REPLY_ID: u64 = 1;
-const FINISH_REMOVING_LIQUIDITY_REPLY_ID: u64 = 2;
-const RESTAKE_SWAP_REPLY_ID: u64 = 3;
-const RESTAKE_ADD_LIQUIDITY_REPLY_ID: u64 = 4;
+// reply ids carry the operation id of the pending state along with one of these kinds
+const ADD_LIQUIDITY_REPLY_KIND: u64 = 1;
+const FINISH_REMOVING_LIQUIDITY_REPLY_KIND: u64 = 2;
+const RESTAKE_SWAP_REPLY_KIND: u64 = 3;
+const RESTAKE_ADD_LIQUIDITY_REPLY_KIND: u64 = 4;
+const LOCK_TOKENS_REPLY_KIND: u64 = 5;
+const SUPERFLUID_LOCK_REPLY_KIND: u64 = 6;
+const JOIN_POOL_REPLY_KIND: u64 = 7;
+const DEPOSIT_SWAP_REPLY_KIND: u64 = 8;
 
 /// Handling contract instantiation
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -52,15 +81,30 @@ pub fn instantiate(
     deps: DepsMut,
     _env: Env,
     info: MessageInfo,
-    _msg: InstantiateMsg,
+    msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
     set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     OWNER.save(deps.storage, &info.sender)?;
+    save_config(deps, msg)?;
     Ok(Response::new())
 }
 
+fn save_config(deps: DepsMut, msg: InstantiateMsg) -> Result<(), ContractError> {
+    if msg.is_superfluid && msg.validator_address.is_none() {
+        return Err(ContractError::ValidationError { val: "Missing validator address".to_string() })
+    }
+    CONFIG.save(deps.storage, &ConfigResponse {
+        beneficiary: deps.api.addr_validate(&msg.beneficiary)?,
+        pool_id: msg.pool_id,
+        duration: msg.duration,
+        is_superfluid: msg.is_superfluid,
+        validator_address: if msg.is_superfluid { msg.validator_address } else { None },
+    })?;
+    Ok(())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+pub fn migrate(deps: DepsMut, _env: Env, msg: MigrateMsg) -> Result<Response, ContractError> {
     let current_version: Version = CONTRACT_VERSION.parse()?;
     let contract_version = get_contract_version(deps.storage)?;
     if contract_version.contract != CONTRACT_NAME {
@@ -73,6 +117,16 @@ pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, C
         // TODO: enable it for prod deployment
         // return Err(ContractError::MigrationError { val: "Not a newer version".to_string() });
     }
+    // single slot reply states are replaced by PENDING_OPS
+    deps.storage.remove(b"deposit_params");
+    deps.storage.remove(b"restake_params");
+    deps.storage.remove(b"receiver");
+    // wallets created before the config existed get it from the vault, their admin
+    if CONFIG.may_load(deps.storage)?.is_none() {
+        let config = msg.config
+            .ok_or_else(|| ContractError::MigrationError { val: "Missing wallet config".to_string() })?;
+        save_config(deps, config)?;
+    }
     Ok(Response::new())
 }
 
@@ -89,20 +143,25 @@ pub fn execute(
             pool_id,
             duration,
             validator_address,
-            share_out_min_amount
-        } => execute::deposit(deps, env, info, pool_id, duration, validator_address, share_out_min_amount),
+            share_out_min_amount,
+            swap,
+        } => execute::deposit(deps, env, info, pool_id, duration, validator_address, share_out_min_amount, swap),
         ExecuteMsg::Restake {
-            add_liquidity: al, duration, swap,
-        } => execute::restake(deps, env, info, al, duration, swap),
+            pool_id, duration, rewards, max_slippage_bps, twap_window_seconds, performance_fee, keeper_fee,
+        } => execute::restake(
+            deps, env, info, pool_id, duration, rewards, max_slippage_bps, twap_window_seconds, performance_fee,
+            keeper_fee,
+        ),
         ExecuteMsg::Unbond {
-            lock_id, is_superfluid_staking,
-        } => execute::unbond(deps, env, info, lock_id, is_superfluid_staking),
+            lock_id, is_superfluid_staking, amount,
+        } => execute::unbond(deps, env, info, lock_id, is_superfluid_staking, amount),
+        ExecuteMsg::UnbondAll {} => execute::unbond_all(deps, env, info),
         ExecuteMsg::Withdraw {
-            receiver, amount, denom
-        } => execute::withdraw(deps, info, receiver, amount, denom),
+            amount, denom
+        } => execute::withdraw(deps, info, amount, denom),
         ExecuteMsg::WithdrawAll {
-            receiver, lp_tokens_out,
-        } => execute::withdraw_all(deps, env, info, receiver, lp_tokens_out),
+            lp_tokens_out,
+        } => execute::withdraw_all(deps, env, info, lp_tokens_out),
     }
 }
 
@@ -117,6 +176,49 @@ pub mod execute {
         Ok(())
     }
 
+    // beneficiary can bypass the vault, funds are always sent to the beneficiary anyway
+    pub fn validate_owner_or_beneficiary(deps: &DepsMut, info: &MessageInfo) -> Result<(), ContractError> {
+        let owner = OWNER.load(deps.storage)?;
+        let config = CONFIG.load(deps.storage)?;
+        if info.sender != owner && info.sender != config.beneficiary {
+            return Err(ContractError::Unauthorized {  });
+        }
+        Ok(())
+    }
+
+    // the wallet only serves the pool and duration it was instantiated with
+    pub fn validate_config(deps: &DepsMut, pool_id: u64, duration: u64) -> Result<ConfigResponse, ContractError> {
+        let config = CONFIG.load(deps.storage)?;
+        if pool_id != config.pool_id {
+            return Err(ContractError::ValidationError { val: format!("Invalid pool id {}", pool_id) })
+        }
+        if duration != config.duration {
+            return Err(ContractError::ValidationError { val: format!("Invalid duration {}", duration) })
+        }
+        Ok(config)
+    }
+
+    pub fn validate_fees(deps: &DepsMut, fees: Vec<&Option<FeeParams>>) -> Result<(), ContractError> {
+        let mut total_fee_bps = 0;
+        for fee in fees.into_iter().flatten() {
+            deps.api.addr_validate(&fee.recipient)?;
+            total_fee_bps += fee.fee_bps;
+        }
+        if total_fee_bps > BPS_DENOMINATOR {
+            return Err(ContractError::ValidationError { val: "Invalid fee".to_string() })
+        }
+        Ok(())
+    }
+
+    fn send_fees(response: Response, fee: Option<FeeParams>, fees: Vec<cosmwasm_std::Coin>, key: &str) -> Response {
+        match fee {
+            Some(fee) if !fees.is_empty() => response
+                .add_attribute(key, coins_to_string(&fees))
+                .add_message(get_transfer_msg(fee.recipient, fees)),
+            _ => response,
+        }
+    }
+
     pub fn validate_funds(info: &MessageInfo) -> Result<cosmwasm_std::Coin, ContractError> {
         if info.funds.len() != 1 || info.funds[0].amount.is_zero() {
             return Err(ContractError::InvalidFunds {  });
@@ -124,13 +226,42 @@ pub mod execute {
         Ok(info.funds[0].clone())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn deposit(
         deps: DepsMut, env: Env, info: MessageInfo, pool_id: u64, duration: u64, validator_address: Option<String>, share_out_min_amount: String,
+        swap: Option<SwapParams>,
     ) -> Result<Response, ContractError> {
         validate_owner(&deps, &info)?;
+        let config = validate_config(&deps, pool_id, duration)?;
+        if config.is_superfluid != validator_address.is_some() {
+            return Err(ContractError::ValidationError { val: "Staking mode mismatch".to_string() })
+        }
+        // restake delegates compounded shares to the validator of the latest deposit
+        if validator_address != config.validator_address {
+            CONFIG.update(deps.storage, |mut config| -> StdResult<ConfigResponse> {
+                config.validator_address = validator_address.clone();
+                Ok(config)
+            })?;
+        }
+        if let Some(swap) = swap {
+            return zap(deps, env, info, pool_id, duration, validator_address, share_out_min_amount, swap);
+        }
+        if info.funds.len() > 1 {
+            return join_pool(deps, env, info, pool_id, duration, validator_address, share_out_min_amount);
+        }
         let fund = validate_funds(&info)?;
-        DEPOSIT_PARAMS_REPLY_STATE.save(deps.storage, &DepositParamsState {
-            pool_id, duration, validator_address,
+        // LP shares of the pool are locked as they are
+        if fund.denom == get_lp_denom(pool_id) {
+            if let Some(validator_address) = validator_address {
+                return superfluid_lock_and_delegate(
+                    deps, &env, duration, fund.amount.to_string(), fund.denom, validator_address,
+                );
+            }
+            return lock(deps, &env, duration, fund.amount.to_string(), fund.denom);
+        }
+        let reply_id = PENDING_OPS.start(deps.storage, env.block.time, ADD_LIQUIDITY_REPLY_KIND, &DepositParamsState {
+            pool_id, duration, validator_address, funds: vec![fund.clone()], share_out_min_amount: share_out_min_amount.clone(),
+            swap: None,
         })?;
         let join_pool_msg = get_add_liquidity_msg(
             env.contract.address.to_string(),
@@ -140,71 +271,362 @@ pub mod execute {
             share_out_min_amount,
         );
         Ok(Response::new()
-            .add_submessage(SubMsg::reply_on_success(join_pool_msg, ADD_LIQUIDITY_REPLY_ID))
+            .add_submessage(SubMsg::reply_on_success(join_pool_msg, reply_id))
         )
     }
 
+    /*
+        Zap deposit: swap the fund into a pool asset, then join and lock from the swap reply
+        The share_out_min_amount of the join bounds the slippage of the whole zap
+    */
+    #[allow(clippy::too_many_arguments)]
+    fn zap(
+        deps: DepsMut, env: Env, info: MessageInfo, pool_id: u64, duration: u64, validator_address: Option<String>,
+        share_out_min_amount: String, swap: SwapParams,
+    ) -> Result<Response, ContractError> {
+        let fund = validate_funds(&info)?;
+        if fund.denom == get_lp_denom(pool_id) {
+            return Err(ContractError::InvalidFunds {  });
+        }
+        let routes = swap.get_routes();
+        if routes.is_empty() {
+            return Err(ContractError::ValidationError { val: "Swap route is empty".to_string() })
+        }
+        let reply_id = PENDING_OPS.start(deps.storage, env.block.time, DEPOSIT_SWAP_REPLY_KIND, &DepositParamsState {
+            pool_id, duration, validator_address, funds: vec![fund.clone()], share_out_min_amount,
+            swap: Some(swap),
+        })?;
+        let swap_msg = get_swap_msg(
+            env.contract.address.to_string(), routes, fund.amount.to_string(), fund.denom, "1".to_string(),
+        );
+        Ok(Response::new()
+            .add_submessage(SubMsg::reply_on_success(swap_msg, reply_id))
+        )
+    }
+
+    /*
+        Multi-asset deposit: join with the shares the funds cover at the pool ratio, funds are the max amounts in
+        The tokens not taken by the pool are refunded to the beneficiary in the reply
+    */
+    fn join_pool(
+        deps: DepsMut, env: Env, info: MessageInfo, pool_id: u64, duration: u64, validator_address: Option<String>,
+        share_out_min_amount: String,
+    ) -> Result<Response, ContractError> {
+        let lp_denom = get_lp_denom(pool_id);
+        if info.funds.iter().any(|fund| fund.amount.is_zero() || fund.denom == lp_denom) {
+            return Err(ContractError::InvalidFunds {  });
+        }
+        let share_out_amount = estimate_join_pool_shares(deps.as_ref(), pool_id, &info.funds)?;
+        if share_out_amount.is_zero() || share_out_amount < Uint128::from_str(&share_out_min_amount)? {
+            return Err(ContractError::AddLiquidityError { val: "Share out amount is too low".to_string() })
+        }
+        let reply_id = PENDING_OPS.start(deps.storage, env.block.time, JOIN_POOL_REPLY_KIND, &DepositParamsState {
+            pool_id, duration, validator_address, funds: info.funds.clone(), share_out_min_amount,
+            swap: None,
+        })?;
+        let join_pool_msg = get_join_pool_msg(
+            env.contract.address.to_string(),
+            pool_id,
+            share_out_amount.to_string(),
+            info.funds,
+        );
+        Ok(Response::new()
+            .add_submessage(SubMsg::reply_on_success(join_pool_msg, reply_id))
+        )
+    }
+
+    /*
+        Reward amounts are resolved up front, fees are skimmed and legs with nothing to restake are skipped
+        Each leg is dispatched from the reply of the previous one, so estimations see the latest pool state
+    */
+    #[allow(clippy::too_many_arguments)]
     pub fn restake(
         deps: DepsMut, env: Env, info: MessageInfo,
-        al: AddLiquidityParams, duration: u64, swap: Option<SwapParams>,
+        pool_id: u64, duration: u64, rewards: Vec<RewardParams>, max_slippage_bps: u64, twap_window_seconds: Option<u64>,
+        performance_fee: Option<FeeParams>, keeper_fee: Option<FeeParams>,
     ) -> Result<Response, ContractError> {
         validate_owner(&deps, &info)?;
-        RESTAKE_REPLY_STATE.save(deps.storage, &RestakeParamsState {
-            pool_id: al.pool_id,
+        validate_config(&deps, pool_id, duration)?;
+        if max_slippage_bps > BPS_DENOMINATOR {
+            return Err(ContractError::ValidationError { val: "Invalid max slippage".to_string() })
+        }
+        validate_fees(&deps, vec![&performance_fee, &keeper_fee])?;
+        let contract_address = env.contract.address.to_string();
+        let mut pending_legs: Vec<RestakeLegState> = vec![];
+        let mut performance_fees: Vec<cosmwasm_std::Coin> = vec![];
+        let mut keeper_fees: Vec<cosmwasm_std::Coin> = vec![];
+        for reward in rewards {
+            let amount = match reward.amount {
+                Some(amount) => Uint128::from_str(&amount)?,
+                None => get_reward_balance(deps.as_ref(), contract_address.clone(), reward.denom.clone(), reward.reserve)?,
+            };
+            if let Some(swap_params) = &reward.swap {
+                if swap_params.get_routes().is_empty() {
+                    return Err(ContractError::SwapError { val: format!("Invalid swap route for {}", reward.denom) })
+                }
+            }
+            let amount = amount
+                - take_fee(amount, &reward.denom, &performance_fee, &mut performance_fees)
+                - take_fee(amount, &reward.denom, &keeper_fee, &mut keeper_fees);
+            if !amount.is_zero() {
+                pending_legs.push(RestakeLegState { amount, denom: reward.denom, swap: reward.swap });
+            }
+        }
+        if pending_legs.is_empty() {
+            return Err(ContractError::ValidationError { val: "Nothing to restake".to_string() })
+        }
+        let mut fee_stats = FEE_STATS.may_load(deps.storage)?.unwrap_or_default();
+        for fee_coin in performance_fees.clone() {
+            add_coin(&mut fee_stats.performance_fees, fee_coin);
+        }
+        for fee_coin in keeper_fees.clone() {
+            add_coin(&mut fee_stats.keeper_fees, fee_coin);
+        }
+        FEE_STATS.save(deps.storage, &fee_stats)?;
+        let response = send_fees(Response::new(), performance_fee, performance_fees, "performance_fee");
+        let response = send_fees(response, keeper_fee, keeper_fees, "keeper_fee");
+        let mut rewards: Vec<cosmwasm_std::Coin> = vec![];
+        for leg in pending_legs.iter() {
+            add_coin(&mut rewards, cosmwasm_std::Coin { denom: leg.denom.clone(), amount: leg.amount });
+        }
+        let restake_response = restake_next_leg(deps, env, None, RestakeParamsState {
+            pool_id,
             duration,
-            share_out_min_amount: al.share_out_min_amount.clone(),
-            swap_denom_out: swap.clone().map(|i| i.denom_out),
+            max_slippage_bps,
+            twap_window_seconds,
+            pending_legs,
+            current_swap: None,
+            share_out_amount: Uint128::zero(),
+            rewards,
         })?;
+        Ok(response
+            .add_submessages(restake_response.messages)
+            .add_attributes(restake_response.attributes)
+        )
+    }
+
+    fn save_restake_state(
+        deps: &mut DepsMut, env: &Env, op_id: Option<u64>, kind: u64, restake_params: &RestakeParamsState,
+    ) -> StdResult<u64> {
+        match op_id {
+            Some(op_id) => PENDING_OPS.next(deps.storage, op_id, env.block.time, kind, restake_params),
+            None => PENDING_OPS.start(deps.storage, env.block.time, kind, restake_params),
+        }
+    }
+
+    // dispatch the next pending leg, or lock all collected LP shares once every leg is done
+    pub fn restake_next_leg(
+        mut deps: DepsMut, env: Env, op_id: Option<u64>, mut restake_params: RestakeParamsState,
+    ) -> Result<Response, ContractError> {
         let contract_address = env.contract.address.to_string();
-        if let Some(swap_params) = swap {
+        if restake_params.pending_legs.is_empty() {
+            if let Some(op_id) = op_id {
+                PENDING_OPS.finish(deps.storage, op_id);
+            }
+            let mut restake_stats = RESTAKE_STATS.may_load(deps.storage)?.unwrap_or_default();
+            restake_stats.last_restake_time = Some(env.block.time);
+            restake_stats.restake_count += 1;
+            restake_stats.shares_added += restake_params.share_out_amount;
+            for reward in restake_params.rewards {
+                add_coin(&mut restake_stats.rewards_compounded, reward);
+            }
+            RESTAKE_STATS.save(deps.storage, &restake_stats)?;
+            let config = CONFIG.load(deps.storage)?;
+            let share_out_amount = restake_params.share_out_amount.to_string();
+            let denom = get_lp_denom(restake_params.pool_id);
+            if let (true, Some(validator_address)) = (config.is_superfluid, config.validator_address) {
+                return superfluid_lock_and_delegate(
+                    deps, &env, restake_params.duration, share_out_amount, denom, validator_address,
+                );
+            }
+            return lock(deps, &env, restake_params.duration, share_out_amount, denom);
+        }
+        let leg = restake_params.pending_legs.remove(0);
+        let amount = leg.amount.to_string();
+        let twap_start_time = restake_params.twap_window_seconds
+            .map(|twap_window_seconds| env.block.time.minus_seconds(twap_window_seconds));
+        if let Some(swap_params) = leg.swap {
+            let routes = swap_params.get_routes();
+            if let Some(start_time) = twap_start_time {
+                validate_swap_prices(deps.as_ref(), &routes, &leg.denom, start_time, restake_params.max_slippage_bps)?;
+            }
+            let amount_out_min = estimate_swap_amount_out_min(
+                deps.as_ref(), contract_address.clone(), routes.clone(), amount.clone(), leg.denom.clone(),
+                restake_params.max_slippage_bps,
+            )?;
             let swap_msg = get_swap_msg(
-                contract_address, swap_params.pool_id, al.amount, al.denom,
-                swap_params.amount_out_min, swap_params.denom_out,
+                contract_address, routes, amount, leg.denom, amount_out_min,
             );
+            restake_params.current_swap = Some(swap_params);
+            let reply_id = save_restake_state(&mut deps, &env, op_id, RESTAKE_SWAP_REPLY_KIND, &restake_params)?;
             Ok(Response::new()
-                .add_submessage(SubMsg::reply_on_success(swap_msg, RESTAKE_SWAP_REPLY_ID)))
+                .add_submessage(SubMsg::reply_on_success(swap_msg, reply_id)))
         } else {
+            if let Some(start_time) = twap_start_time {
+                validate_join_prices(
+                    deps.as_ref(), restake_params.pool_id, &leg.denom, start_time, restake_params.max_slippage_bps,
+                )?;
+            }
+            let share_out_min_amount = estimate_join_share_out_min(
+                deps.as_ref(), restake_params.pool_id, amount.clone(), leg.denom.clone(), restake_params.max_slippage_bps,
+            )?;
             let add_liquidity_msg = get_add_liquidity_msg(
-                contract_address, al.pool_id, al.amount, al.denom, al.share_out_min_amount
+                contract_address, restake_params.pool_id, amount, leg.denom, share_out_min_amount
             );
+            restake_params.current_swap = None;
+            let reply_id = save_restake_state(
+                &mut deps, &env, op_id, RESTAKE_ADD_LIQUIDITY_REPLY_KIND, &restake_params,
+            )?;
             Ok(Response::new()
-                .add_submessage(SubMsg::reply_on_success(add_liquidity_msg, RESTAKE_ADD_LIQUIDITY_REPLY_ID))
+                .add_submessage(SubMsg::reply_on_success(add_liquidity_msg, reply_id))
             )
         }
     }
 
-    pub fn unbond(deps: DepsMut, env: Env, info: MessageInfo, lock_id: u64, is_superfluid_staking: bool) -> Result<Response, ContractError> {
-        validate_owner(&deps, &info)?;
+    // locks unbond for their own duration, matured unbondings are dropped when a new one is recorded
+    fn save_unbonding(
+        storage: &mut dyn cosmwasm_std::Storage, env: &Env, lock_id: Option<u64>, lock: &LockInfo, amount: Uint128,
+    ) -> StdResult<()> {
+        let matured_ids: Vec<u64> = UNBONDINGS
+            .range(storage, None, None, Order::Ascending)
+            .filter_map(|item| match item {
+                Ok((unbonding_id, unbonding)) if unbonding.end_time <= env.block.time => Some(Ok(unbonding_id)),
+                Ok(_) => None,
+                Err(err) => Some(Err(err)),
+            })
+            .collect::<StdResult<_>>()?;
+        for unbonding_id in matured_ids {
+            UNBONDINGS.remove(storage, unbonding_id);
+        }
+        let unbonding_id = LAST_UNBONDING_ID.may_load(storage)?.unwrap_or_default() + 1;
+        LAST_UNBONDING_ID.save(storage, &unbonding_id)?;
+        UNBONDINGS.save(storage, unbonding_id, &UnbondingInfo {
+            lock_id,
+            amount,
+            denom: lock.denom.clone(),
+            is_superfluid: lock.is_superfluid,
+            end_time: env.block.time.plus_seconds(lock.duration),
+        })
+    }
+
+    pub fn unbond(
+        deps: DepsMut, env: Env, info: MessageInfo, lock_id: u64, is_superfluid_staking: bool, amount: Option<String>,
+    ) -> Result<Response, ContractError> {
+        validate_owner_or_beneficiary(&deps, &info)?;
         let contract_address = env.contract.address.to_string();
+        let lock = LOCKS.may_load(deps.storage, lock_id)?;
+        if let Some(amount) = amount {
+            let amount = Uint128::from_str(&amount)?;
+            let mut lock = lock.ok_or_else(|| ContractError::ValidationError { val: "Unknown lock".to_string() })?;
+            if is_superfluid_staking || lock.is_superfluid {
+                return Err(ContractError::ValidationError {
+                    val: "Partial unbonding is not supported for superfluid locks".to_string()
+                })
+            }
+            if amount.is_zero() || amount > lock.amount {
+                return Err(ContractError::ValidationError { val: "Invalid unbonding amount".to_string() })
+            }
+            // Osmosis moves the unlocking part into a new lock, the rest stays under lock_id
+            let unbonding_lock_id = if amount == lock.amount { Some(lock_id) } else { None };
+            save_unbonding(deps.storage, &env, unbonding_lock_id, &lock, amount)?;
+            lock.amount -= amount;
+            if lock.amount.is_zero() {
+                LOCKS.remove(deps.storage, lock_id);
+            } else {
+                LOCKS.save(deps.storage, lock_id, &lock)?;
+            }
+            let coins = vec![cosmwasm_std::Coin { denom: lock.denom, amount }];
+            return unlock(contract_address, lock_id, coins);
+        }
+        // locks created before the registry are not tracked
+        if let Some(lock) = lock {
+            save_unbonding(deps.storage, &env, Some(lock_id), &lock, lock.amount)?;
+            LOCKS.remove(deps.storage, lock_id);
+        }
         if is_superfluid_staking {
             superfluid_undelegate_and_unbond(contract_address, lock_id)
         } else {
-            unlock(contract_address, lock_id)
+            unlock(contract_address, lock_id, vec![])
         }
     }
 
-    pub fn lock(owner: String, duration: u64, amount: String, denom: String) -> Result<Response, ContractError> {
-        let lock_msg = get_lock_tokens_msg(owner, duration, amount, denom);
+    pub fn lock(deps: DepsMut, env: &Env, duration: u64, amount: String, denom: String) -> Result<Response, ContractError> {
+        let reply_id = PENDING_OPS.start(deps.storage, env.block.time, LOCK_TOKENS_REPLY_KIND, &LockParamsState {
+            amount: Uint128::from_str(&amount)?,
+            denom: denom.clone(),
+            duration,
+            is_superfluid: false,
+        })?;
+        let lock_msg = get_lock_tokens_msg(env.contract.address.to_string(), duration, amount, denom);
         Ok(Response::new()
-            .add_message(lock_msg)
+            .add_submessage(SubMsg::reply_on_success(lock_msg, reply_id))
         )
     }
 
-    pub fn unlock(owner: String, lock_id: u64) -> Result<Response, ContractError> {
-        let unlock_msg = get_unlock_msg(owner, lock_id);
+    pub fn unbond_all(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
+        validate_owner_or_beneficiary(&deps, &info)?;
+        let contract_address = env.contract.address.to_string();
+        // the chain is the source of truth, locks created before the registry are not in LOCKS
+        let account_locks = get_account_locks(deps.as_ref(), contract_address.clone())?;
+        if account_locks.is_empty() {
+            return Err(ContractError::ValidationError { val: "No lock to unbond".to_string() })
+        }
+        let config = CONFIG.load(deps.storage)?;
+        let mut response = Response::new().add_attribute("action", "unbond_all");
+        for (lock_id, coin, duration) in account_locks {
+            let lock = match LOCKS.may_load(deps.storage, lock_id)? {
+                Some(lock) => LockInfo { amount: coin.amount, duration, ..lock },
+                // untracked locks were created in the staking mode of the wallet
+                None => LockInfo {
+                    amount: coin.amount,
+                    denom: coin.denom,
+                    duration,
+                    is_superfluid: config.is_superfluid,
+                    created_at: env.block.time,
+                },
+            };
+            save_unbonding(deps.storage, &env, Some(lock_id), &lock, lock.amount)?;
+            LOCKS.remove(deps.storage, lock_id);
+            let unbond_response = if lock.is_superfluid {
+                superfluid_undelegate_and_unbond(contract_address.clone(), lock_id)?
+            } else {
+                unlock(contract_address.clone(), lock_id, vec![])?
+            };
+            response = response
+                .add_submessages(unbond_response.messages)
+                .add_attribute("lock_id", lock_id.to_string());
+        }
+        // entries left in the registry are no longer locked on chain
+        let stale_ids: Vec<u64> = LOCKS
+            .keys(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        for lock_id in stale_ids {
+            LOCKS.remove(deps.storage, lock_id);
+        }
+        Ok(response)
+    }
+
+    pub fn unlock(owner: String, lock_id: u64, coins: Vec<cosmwasm_std::Coin>) -> Result<Response, ContractError> {
+        let unlock_msg = get_unlock_msg(owner, lock_id, coins);
         Ok(Response::new()
             .add_message(unlock_msg)
         )
     }
 
     pub fn superfluid_lock_and_delegate(
-        owner: String, amount: String, denom: String, validator_address: String
+        deps: DepsMut, env: &Env, duration: u64, amount: String, denom: String, validator_address: String
     ) -> Result<Response, ContractError> {
+        let reply_id = PENDING_OPS.start(deps.storage, env.block.time, SUPERFLUID_LOCK_REPLY_KIND, &LockParamsState {
+            amount: Uint128::from_str(&amount)?,
+            denom: denom.clone(),
+            duration,
+            is_superfluid: true,
+        })?;
         let lock_and_delegate_msg = get_superfluid_lock_and_delegate_msg(
-            owner, amount, denom, validator_address,
+            env.contract.address.to_string(), amount, denom, validator_address,
         );
         Ok(Response::new()
-            .add_message(lock_and_delegate_msg)
+            .add_submessage(SubMsg::reply_on_success(lock_and_delegate_msg, reply_id))
         )
     }
 
@@ -218,11 +640,11 @@ pub mod execute {
     }
 
     pub fn withdraw(
-        deps: DepsMut, info: MessageInfo, receiver: String, amount: String, denom: String,
+        deps: DepsMut, info: MessageInfo, amount: String, denom: String,
     ) -> Result<Response, ContractError> {
-        validate_owner(&deps, &info)?;
-        deps.api.addr_validate(&receiver)?;
-        let send_msg = get_single_transfer_msg(receiver, amount, denom); 
+        validate_owner_or_beneficiary(&deps, &info)?;
+        let config = CONFIG.load(deps.storage)?;
+        let send_msg = get_single_transfer_msg(config.beneficiary.to_string(), amount, denom);
         Ok(Response::new()
             .add_message(send_msg)
         )
@@ -238,16 +660,19 @@ pub mod execute {
     /* 
         Break all lp token inside lp_tokens_out to single denom_out first
         If lp_tokens_out has multiple values, only add the reply callback for the last element
-        After receiveing the reply, transfer all tokens to the receiver
+        After receiveing the reply, transfer all tokens to the beneficiary
     */
-    pub fn withdraw_all(deps: DepsMut, env: Env, info: MessageInfo, receiver: String, lp_tokens_out: Option<Vec<RemoveLiquidityParams>>) -> Result<Response, ContractError> {
-        validate_owner(&deps, &info)?;
-        deps.api.addr_validate(&receiver)?;
+    pub fn withdraw_all(deps: DepsMut, env: Env, info: MessageInfo, lp_tokens_out: Option<Vec<RemoveLiquidityParams>>) -> Result<Response, ContractError> {
+        validate_owner_or_beneficiary(&deps, &info)?;
+        let config = CONFIG.load(deps.storage)?;
+        let receiver = config.beneficiary.to_string();
         if let Some(mut removing_lp_tokens) = lp_tokens_out {
+            if removing_lp_tokens.iter().any(|lp_token| lp_token.pool_id != config.pool_id) {
+                return Err(ContractError::ValidationError { val: "Invalid pool id".to_string() })
+            }
             if removing_lp_tokens.is_empty() {
                 return send_all_balances(deps, env, receiver)
             }
-            RECEIVER_REPLY_STATE.save(deps.storage, &receiver)?;
             let lp_token = removing_lp_tokens.pop().unwrap();
             let finish_removing_liquidity_msg = get_remove_liquidity_msg(
                 env.contract.address.to_string(),
@@ -267,7 +692,9 @@ pub mod execute {
                 )).collect();
             Ok(Response::new()
                 .add_messages(removing_liquidity_msgs)
-                .add_submessage(SubMsg::reply_on_success(finish_removing_liquidity_msg, FINISH_REMOVING_LIQUIDITY_REPLY_ID))
+                .add_submessage(SubMsg::reply_on_success(
+                    finish_removing_liquidity_msg, encode_reply_id(0, FINISH_REMOVING_LIQUIDITY_REPLY_KIND),
+                ))
             )
         } else {
             send_all_balances(deps, env, receiver)
@@ -280,7 +707,13 @@ pub mod execute {
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::GetOwner {  } => to_binary(&query::get_owner(deps)?),
+        QueryMsg::GetConfig {  } => to_binary(&query::get_config(deps)?),
         QueryMsg::Test {  } => to_binary(&query::test(deps, env)?),
+        QueryMsg::GetLocks {  } => to_binary(&query::get_locks(deps)?),
+        QueryMsg::GetUnbonding {  } => to_binary(&query::get_unbonding(deps)?),
+        QueryMsg::GetFeeStats {  } => to_binary(&query::get_fee_stats(deps)?),
+        QueryMsg::GetRestakeStats {  } => to_binary(&query::get_restake_stats(deps)?),
+        QueryMsg::GetPendingOps { start_after, limit } => to_binary(&query::get_pending_ops(deps, start_after, limit)?),
     }
 }
 
@@ -298,6 +731,56 @@ pub mod query {
         OWNER.load(deps.storage)
     }
 
+    pub fn get_config(deps: Deps) -> StdResult<ConfigResponse> {
+        CONFIG.load(deps.storage)
+    }
+
+    pub fn get_fee_stats(deps: Deps) -> StdResult<FeeStats> {
+        Ok(FEE_STATS.may_load(deps.storage)?.unwrap_or_default())
+    }
+
+    pub fn get_restake_stats(deps: Deps) -> StdResult<RestakeStats> {
+        Ok(RESTAKE_STATS.may_load(deps.storage)?.unwrap_or_default())
+    }
+
+    pub fn get_pending_ops(deps: Deps, start_after: Option<u64>, limit: u64) -> StdResult<Vec<PendingOp>> {
+        PENDING_OPS.range(deps.storage, start_after, limit)
+    }
+
+    pub fn get_unbonding(deps: Deps) -> StdResult<Vec<UnbondingResponse>> {
+        UNBONDINGS
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| {
+                let (unbonding_id, unbonding) = item?;
+                Ok(UnbondingResponse {
+                    unbonding_id,
+                    lock_id: unbonding.lock_id,
+                    amount: unbonding.amount,
+                    denom: unbonding.denom,
+                    is_superfluid: unbonding.is_superfluid,
+                    end_time: unbonding.end_time,
+                })
+            })
+            .collect()
+    }
+
+    pub fn get_locks(deps: Deps) -> StdResult<Vec<LockResponse>> {
+        LOCKS
+            .range(deps.storage, None, None, Order::Ascending)
+            .map(|item| {
+                let (lock_id, lock) = item?;
+                Ok(LockResponse {
+                    lock_id,
+                    amount: lock.amount,
+                    denom: lock.denom,
+                    duration: lock.duration,
+                    is_superfluid: lock.is_superfluid,
+                    created_at: lock.created_at,
+                })
+            })
+            .collect()
+    }
+
     pub fn test(deps: Deps, _env: Env) -> StdResult<QuerySwapExactAmountInResponse> {
         let routes = vec![SwapAmountInRoute {
             pool_id: 1,
@@ -319,35 +802,43 @@ pub mod query {
 pub fn reply(
     deps: DepsMut, env: Env, msg: Reply
 ) -> Result<Response, ContractError> {
-    match msg.id {
-        ADD_LIQUIDITY_REPLY_ID => reply::handle_add_liquidity(deps, env, msg),
-        FINISH_REMOVING_LIQUIDITY_REPLY_ID => reply::handle_remove_liquidity(deps, env, msg),
-        RESTAKE_SWAP_REPLY_ID => reply::handle_swap(deps, env, msg),
-        RESTAKE_ADD_LIQUIDITY_REPLY_ID => reply::handle_restake_add_liquidity(deps, env, msg),
+    let (op_id, kind) = decode_reply_id(msg.id);
+    match kind {
+        ADD_LIQUIDITY_REPLY_KIND => reply::handle_add_liquidity(deps, env, op_id, msg),
+        FINISH_REMOVING_LIQUIDITY_REPLY_KIND => reply::handle_remove_liquidity(deps, env, msg),
+        RESTAKE_SWAP_REPLY_KIND | DEPOSIT_SWAP_REPLY_KIND => reply::handle_swap(deps, env, op_id, kind, msg),
+        RESTAKE_ADD_LIQUIDITY_REPLY_KIND => reply::handle_restake_add_liquidity(deps, env, op_id, msg),
+        LOCK_TOKENS_REPLY_KIND => reply::handle_lock_tokens(deps, env, op_id, msg),
+        SUPERFLUID_LOCK_REPLY_KIND => reply::handle_superfluid_lock(deps, env, op_id, msg),
+        JOIN_POOL_REPLY_KIND => reply::handle_join_pool(deps, env, op_id, msg),
         _id => Err(ContractError::CustomError { val: format!("Unknow reply id {}", msg.id) }),
     }
 }
 
 pub mod reply {
     use osmosis_std::types::osmosis::gamm::v1beta1::{
-        MsgJoinSwapExternAmountInResponse, MsgExitSwapShareAmountInResponse, MsgSwapExactAmountInResponse
+        MsgJoinSwapExternAmountInResponse, MsgExitSwapShareAmountInResponse, MsgSwapExactAmountInResponse,
+        MsgJoinPoolResponse,
     };
+    use osmosis_std::types::osmosis::lockup::MsgLockTokensResponse;
+    use osmosis_std::types::osmosis::superfluid::MsgLockAndSuperfluidDelegateResponse;
     use super::*;
 
     pub fn handle_add_liquidity(
-        deps: DepsMut, env: Env, msg: Reply
+        deps: DepsMut, env: Env, op_id: u64, msg: Reply
     ) -> Result<Response, ContractError> {
         if let SubMsgResult::Ok(SubMsgResponse { data, .. }) = msg.result.clone() {
             if let Some(b) = data {
-                let deposit_params = DEPOSIT_PARAMS_REPLY_STATE.load(deps.storage)?;
+                let deposit_params: DepositParamsState = PENDING_OPS.load(deps.storage, op_id)?;
                 let response: MsgJoinSwapExternAmountInResponse = b.try_into().map_err(ContractError::Std)?;
                 let denom = get_lp_denom(deposit_params.pool_id);
-                let contract_address = env.contract.address.to_string();
-                DEPOSIT_PARAMS_REPLY_STATE.remove(deps.storage);
+                PENDING_OPS.finish(deps.storage, op_id);
                 if let Some(validator_address) = deposit_params.validator_address {
-                    return execute::superfluid_lock_and_delegate(contract_address, response.share_out_amount, denom, validator_address);
+                    return execute::superfluid_lock_and_delegate(
+                        deps, &env, deposit_params.duration, response.share_out_amount, denom, validator_address
+                    );
                 } else {
-                    return execute::lock(contract_address, deposit_params.duration, response.share_out_amount, denom);
+                    return execute::lock(deps, &env, deposit_params.duration, response.share_out_amount, denom);
                 }
             } else {
                 return Err(ContractError::AddLiquidityError { val: "Empty response".to_string() })
@@ -356,21 +847,99 @@ pub mod reply {
         Err(ContractError::AddLiquidityError { val: msg.result.unwrap_err() })
     }
 
+    pub fn handle_join_pool(
+        deps: DepsMut, env: Env, op_id: u64, msg: Reply
+    ) -> Result<Response, ContractError> {
+        if let SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) = msg.result {
+            let deposit_params: DepositParamsState = PENDING_OPS.load(deps.storage, op_id)?;
+            let response: MsgJoinPoolResponse = b.try_into().map_err(ContractError::Std)?;
+            PENDING_OPS.finish(deps.storage, op_id);
+            let mut refunds: Vec<cosmwasm_std::Coin> = vec![];
+            for fund in deposit_params.funds {
+                let used_amount = match response.token_in.iter().find(|token| token.denom == fund.denom) {
+                    Some(token) => Uint128::from_str(&token.amount)?,
+                    None => Uint128::zero(),
+                };
+                add_coin(&mut refunds, cosmwasm_std::Coin {
+                    denom: fund.denom,
+                    amount: fund.amount.saturating_sub(used_amount),
+                });
+            }
+            let config = CONFIG.load(deps.storage)?;
+            let denom = get_lp_denom(deposit_params.pool_id);
+            let lock_response = if let Some(validator_address) = deposit_params.validator_address {
+                execute::superfluid_lock_and_delegate(
+                    deps, &env, deposit_params.duration, response.share_out_amount, denom, validator_address
+                )?
+            } else {
+                execute::lock(deps, &env, deposit_params.duration, response.share_out_amount, denom)?
+            };
+            if refunds.is_empty() {
+                return Ok(lock_response)
+            }
+            return Ok(lock_response
+                .add_message(get_transfer_msg(config.beneficiary.to_string(), refunds.clone()))
+                .add_attribute("refund", coins_to_string(&refunds))
+            )
+        }
+        Err(ContractError::AddLiquidityError {
+            val: msg.result.unwrap_err(),
+        })
+    }
+
+    fn get_swap_denom_out(swap: Option<SwapParams>) -> Result<String, ContractError> {
+        swap
+            .and_then(|swap_params| swap_params.get_denom_out())
+            .ok_or_else(|| ContractError::SwapError { val: "Missing swap route".to_string() })
+    }
+
+    // the swapped tokens join the pool, then the deposit or the restake continues from the add liquidity reply
     pub fn handle_swap(
-        deps: DepsMut, env: Env, msg: Reply,
+        deps: DepsMut, env: Env, op_id: u64, kind: u64, msg: Reply,
     ) -> Result<Response, ContractError> {
         if let SubMsgResult::Ok(SubMsgResponse { data: Some(b), .. }) = msg.result {
-            let restake_params = RESTAKE_REPLY_STATE.load(deps.storage)?;
             let swap_result: MsgSwapExactAmountInResponse = b.try_into().map_err(ContractError::Std)?;
+            if kind == DEPOSIT_SWAP_REPLY_KIND {
+                let deposit_params: DepositParamsState = PENDING_OPS.load(deps.storage, op_id)?;
+                let denom_out = get_swap_denom_out(deposit_params.swap.clone())?;
+                let add_liquidity_msg = get_add_liquidity_msg(
+                    env.contract.address.to_string(),
+                    deposit_params.pool_id,
+                    swap_result.token_out_amount,
+                    denom_out,
+                    deposit_params.share_out_min_amount.clone(),
+                );
+                let reply_id = PENDING_OPS.next(
+                    deps.storage, op_id, env.block.time, ADD_LIQUIDITY_REPLY_KIND, &deposit_params,
+                )?;
+                return Ok(Response::new()
+                    .add_submessage(SubMsg::reply_on_success(add_liquidity_msg, reply_id))
+                )
+            }
+            let restake_params: RestakeParamsState = PENDING_OPS.load(deps.storage, op_id)?;
+            let denom_out = get_swap_denom_out(restake_params.current_swap.clone())?;
+            if let Some(twap_window_seconds) = restake_params.twap_window_seconds {
+                validate_join_prices(
+                    deps.as_ref(), restake_params.pool_id, &denom_out, env.block.time.minus_seconds(twap_window_seconds),
+                    restake_params.max_slippage_bps,
+                )?;
+            }
+            let share_out_min_amount = estimate_join_share_out_min(
+                deps.as_ref(), restake_params.pool_id, swap_result.token_out_amount.clone(), denom_out.clone(),
+                restake_params.max_slippage_bps,
+            )?;
             let add_liquidity_msg = get_add_liquidity_msg(
                 env.contract.address.to_string(),
                 restake_params.pool_id,
                 swap_result.token_out_amount,
-                restake_params.swap_denom_out.unwrap(),
-                restake_params.share_out_min_amount
+                denom_out,
+                share_out_min_amount,
             );
+            let reply_id = PENDING_OPS.next(
+                deps.storage, op_id, env.block.time, RESTAKE_ADD_LIQUIDITY_REPLY_KIND, &restake_params,
+            )?;
             return Ok(Response::new()
-                .add_submessage(SubMsg::reply_on_success(add_liquidity_msg, RESTAKE_ADD_LIQUIDITY_REPLY_ID))
+                .add_submessage(SubMsg::reply_on_success(add_liquidity_msg, reply_id))
             )
         }
         Err(ContractError::SwapError {
@@ -382,9 +951,8 @@ pub mod reply {
         if let SubMsgResult::Ok(SubMsgResponse { data, .. }) = msg.result.clone() {
             if let Some(b) = data {
                 let _response: MsgExitSwapShareAmountInResponse = b.try_into().map_err(ContractError::Std)?;
-                let receiver = RECEIVER_REPLY_STATE.load(deps.storage)?;
-                RECEIVER_REPLY_STATE.remove(deps.storage);
-                return execute::send_all_balances(deps, env, receiver);
+                let config = CONFIG.load(deps.storage)?;
+                return execute::send_all_balances(deps, env, config.beneficiary.to_string());
                
             } else {
                 return Err(ContractError::RemoveLiquidityError { val: "Empty response".to_string() })
@@ -393,21 +961,65 @@ pub mod reply {
         Err(ContractError::RemoveLiquidityError { val: msg.result.unwrap_err() })
     }
 
-    pub fn handle_restake_add_liquidity(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    pub fn handle_restake_add_liquidity(deps: DepsMut, env: Env, op_id: u64, msg: Reply) -> Result<Response, ContractError> {
         if let SubMsgResult::Ok(SubMsgResponse { data, .. }) = msg.result.clone() {
             if let Some(b) = data {
-                let restake_params = RESTAKE_REPLY_STATE.load(deps.storage)?;
+                let mut restake_params: RestakeParamsState = PENDING_OPS.load(deps.storage, op_id)?;
                 let response: MsgJoinSwapExternAmountInResponse = b.try_into().map_err(ContractError::Std)?;
-                
-                let contract_address = env.contract.address.to_string();
-                let denom = get_lp_denom(restake_params.pool_id);
-
-                RESTAKE_REPLY_STATE.remove(deps.storage);
-                return execute::lock(contract_address, restake_params.duration, response.share_out_amount, denom);
+                restake_params.share_out_amount += Uint128::from_str(&response.share_out_amount)?;
+                return execute::restake_next_leg(deps, env, Some(op_id), restake_params);
             } else {
                 return Err(ContractError::AddLiquidityError { val: "Empty response".to_string() })
             }
         }
         Err(ContractError::AddLiquidityError { val: msg.result.unwrap_err() })
     }
+
+    // Osmosis adds tokens to an existing lock with the same owner, denom and duration,
+    // so the returned lock id may already be recorded
+    fn save_lock(deps: DepsMut, env: Env, op_id: u64, lock_id: u64) -> Result<Response, ContractError> {
+        let lock_params: LockParamsState = PENDING_OPS.load(deps.storage, op_id)?;
+        PENDING_OPS.finish(deps.storage, op_id);
+        // the registry mirrors the lock on chain, which may hold tokens locked before the registry existed
+        let (amount, duration) = get_lock_amount_and_duration(deps.as_ref(), lock_id, &lock_params.denom)?;
+        LOCKS.update(deps.storage, lock_id, |lock| -> StdResult<LockInfo> {
+            Ok(LockInfo {
+                amount,
+                denom: lock_params.denom.clone(),
+                duration,
+                is_superfluid: lock_params.is_superfluid,
+                created_at: lock.map(|lock| lock.created_at).unwrap_or(env.block.time),
+            })
+        })?;
+        Ok(Response::new()
+            .add_attribute("action", "lock")
+            .add_attribute("lock_id", lock_id.to_string())
+            .add_attribute("amount", lock_params.amount.to_string())
+            .add_attribute("denom", lock_params.denom)
+        )
+    }
+
+    pub fn handle_lock_tokens(deps: DepsMut, env: Env, op_id: u64, msg: Reply) -> Result<Response, ContractError> {
+        if let SubMsgResult::Ok(SubMsgResponse { data, .. }) = msg.result.clone() {
+            if let Some(b) = data {
+                let response: MsgLockTokensResponse = b.try_into().map_err(ContractError::Std)?;
+                return save_lock(deps, env, op_id, response.id);
+            } else {
+                return Err(ContractError::LockError { val: "Empty response".to_string() })
+            }
+        }
+        Err(ContractError::LockError { val: msg.result.unwrap_err() })
+    }
+
+    pub fn handle_superfluid_lock(deps: DepsMut, env: Env, op_id: u64, msg: Reply) -> Result<Response, ContractError> {
+        if let SubMsgResult::Ok(SubMsgResponse { data, .. }) = msg.result.clone() {
+            if let Some(b) = data {
+                let response: MsgLockAndSuperfluidDelegateResponse = b.try_into().map_err(ContractError::Std)?;
+                return save_lock(deps, env, op_id, response.id);
+            } else {
+                return Err(ContractError::LockError { val: "Empty response".to_string() })
+            }
+        }
+        Err(ContractError::LockError { val: msg.result.unwrap_err() })
+    }
 }
\ No newline at end of file
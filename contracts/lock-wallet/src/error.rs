@@ -9,6 +9,9 @@ pub enum ContractError {
     #[error("Unauthorized")]
     Unauthorized {},
 
+    #[error("ValidationError: {val:?}")]
+    ValidationError { val: String },
+
     #[error("InvalidFunds")]
     InvalidFunds {},
 
@@ -21,6 +24,9 @@ pub enum ContractError {
     #[error["Fail to remove liquidity: {val:?}"]]
     RemoveLiquidityError { val: String },
 
+    #[error["Fail to lock: {val:?}"]]
+    LockError { val: String },
+
     #[error("Semver parsing error: {0}")]
     SemVer(#[from] semver::Error),
 
@@ -1,3 +1,208 @@
+use std::str::FromStr;
+
+use cosmwasm_std::{Decimal, Deps, StdError, StdResult, Timestamp, Uint128, Coin as CwCoin};
+use osmosis_std::types::cosmos::base::v1beta1::Coin;
+use osmosis_std::types::osmosis::gamm::v1beta1::GammQuerier;
+use osmosis_std::types::osmosis::gamm::v2::GammQuerier as GammV2Querier;
+use osmosis_std::types::osmosis::twap::v1beta1::TwapQuerier;
+use osmosis_std::types::osmosis::lockup::LockupQuerier;
+use osmosis_std::shim::{Duration, Timestamp as OsmosisTimestamp};
+use common::types::{SwapRoute, FeeParams, BPS_DENOMINATOR, LP_DENOM_PREFIX};
+use common::coins::add_coin;
+use crate::osmosis_msg::get_swap_routes;
+
 pub fn get_lp_denom(pool_id: u64) -> String {
-  format!("gamm/pool/{}", pool_id)
-}
\ No newline at end of file
+  format!("{}{}", LP_DENOM_PREFIX, pool_id)
+}
+
+// whole balance of a reward denom minus the reserve, LP shares are never counted as rewards
+pub fn get_reward_balance(
+  deps: Deps, address: String, denom: String, reserve: Option<String>,
+) -> StdResult<Uint128> {
+  let balance = deps.querier.query_all_balances(address)?
+    .into_iter()
+    .filter(|coin| !coin.denom.starts_with(LP_DENOM_PREFIX))
+    .find(|coin| coin.denom == denom)
+    .map(|coin| coin.amount)
+    .unwrap_or_default();
+  let reserve = match reserve {
+    Some(reserve) => Uint128::from_str(&reserve)?,
+    None => Uint128::zero(),
+  };
+  Ok(balance.saturating_sub(reserve))
+}
+
+// fee share of amount, recorded into fees
+pub fn take_fee(amount: Uint128, denom: &str, fee: &Option<FeeParams>, fees: &mut Vec<CwCoin>) -> Uint128 {
+  if let Some(fee) = fee {
+    let fee_amount = amount.multiply_ratio(fee.fee_bps, BPS_DENOMINATOR);
+    add_coin(fees, CwCoin { denom: denom.to_string(), amount: fee_amount });
+    fee_amount
+  } else {
+    Uint128::zero()
+  }
+}
+
+pub fn apply_slippage(amount: Uint128, max_slippage_bps: u64) -> Uint128 {
+  amount.multiply_ratio(BPS_DENOMINATOR - max_slippage_bps, BPS_DENOMINATOR)
+}
+
+// minimum swap output, computed from the pool estimation minus the allowed slippage
+pub fn estimate_swap_amount_out_min(
+  deps: Deps, sender: String, routes: Vec<SwapRoute>, amount_in: String, denom_in: String, max_slippage_bps: u64,
+) -> StdResult<String> {
+  let pool_id = routes.first().map(|route| route.pool_id).unwrap_or_default();
+  let res = GammQuerier::new(&deps.querier)
+    .estimate_swap_exact_amount_in(sender, pool_id, format!("{}{}", amount_in, denom_in), get_swap_routes(routes))?;
+  let amount_out = Uint128::from_str(&res.token_out_amount)?;
+  Ok(apply_slippage(amount_out, max_slippage_bps).to_string())
+}
+
+// minimum shares of a single sided join, computed from the pool estimation minus the allowed slippage
+pub fn estimate_join_share_out_min(
+  deps: Deps, pool_id: u64, amount: String, denom: String, max_slippage_bps: u64,
+) -> StdResult<String> {
+  let res = GammQuerier::new(&deps.querier)
+    .calc_join_pool_shares(pool_id, vec![Coin { denom, amount }])?;
+  let share_out_amount = Uint128::from_str(&res.share_out_amount)?;
+  Ok(apply_slippage(share_out_amount, max_slippage_bps).to_string())
+}
+
+/*
+  Shares for joining without a swap, MsgJoinPool takes the pool assets at the current pool ratio
+  so the scarcest fund relative to its pool asset bounds the shares, the rest of the funds is not taken
+*/
+pub fn estimate_join_pool_shares(
+  deps: Deps, pool_id: u64, tokens_in: &[CwCoin],
+) -> StdResult<Uint128> {
+  let querier = GammQuerier::new(&deps.querier);
+  let total_shares = match querier.total_shares(pool_id)?.total_shares {
+    Some(coin) => Uint128::from_str(&coin.amount)?,
+    None => Uint128::zero(),
+  };
+  let liquidity = querier.total_pool_liquidity(pool_id)?.liquidity;
+  if tokens_in.iter().any(|token| !liquidity.iter().any(|asset| asset.denom == token.denom)) {
+    return Err(StdError::generic_err("Fund is not a pool asset"));
+  }
+  let mut share_out_amount: Option<Uint128> = None;
+  for asset in liquidity {
+    let asset_amount = Uint128::from_str(&asset.amount)?;
+    if asset_amount.is_zero() {
+      return Ok(Uint128::zero());
+    }
+    let fund_amount = tokens_in
+      .iter()
+      .find(|token| token.denom == asset.denom)
+      .map(|token| token.amount)
+      .unwrap_or_default();
+    let shares = fund_amount.multiply_ratio(total_shares, asset_amount);
+    share_out_amount = Some(share_out_amount.map_or(shares, |amount| amount.min(shares)));
+  }
+  Ok(share_out_amount.unwrap_or_default())
+}
+
+// amount of denom and duration of a lock on chain, locks merge with earlier ones of the same
+// owner, denom and duration and superfluid locks use the unbonding period instead of the wallet duration
+pub fn get_lock_amount_and_duration(deps: Deps, lock_id: u64, denom: &str) -> StdResult<(Uint128, u64)> {
+  let lock = LockupQuerier::new(&deps.querier).locked_by_id(lock_id)?.lock
+    .ok_or_else(|| StdError::generic_err(format!("Lock {} not found", lock_id)))?;
+  let duration = lock.duration
+    .ok_or_else(|| StdError::generic_err(format!("Lock {} has no duration", lock_id)))?;
+  let amount = match lock.coins.iter().find(|coin| coin.denom == denom) {
+    Some(coin) => Uint128::from_str(&coin.amount)?,
+    None => Uint128::zero(),
+  };
+  Ok((amount, duration.seconds as u64))
+}
+
+// (lock_id, coin, duration) of the account locks not unlocking yet, LP locks hold a single coin
+pub fn get_account_locks(deps: Deps, owner: String) -> StdResult<Vec<(u64, CwCoin, u64)>> {
+  let locks = LockupQuerier::new(&deps.querier)
+    .account_locked_longer_duration(owner, Some(Duration { seconds: 0, nanos: 0 }))?
+    .locks;
+  let mut account_locks = vec![];
+  for lock in locks {
+    let duration = lock.duration.map(|duration| duration.seconds as u64).unwrap_or_default();
+    if let Some(coin) = lock.coins.first() {
+      let coin = CwCoin { denom: coin.denom.clone(), amount: Uint128::from_str(&coin.amount)? };
+      account_locks.push((lock.id, coin, duration));
+    }
+  }
+  Ok(account_locks)
+}
+
+/*
+  Spot price of the pool within max_deviation_bps of its arithmetic TWAP since start_time
+  Minimums estimated from the spot state are only safe if the price was not moved earlier in the transaction
+*/
+pub fn validate_spot_price(
+  deps: Deps, pool_id: u64, base_denom: &str, quote_denom: &str, start_time: Timestamp, max_deviation_bps: u64,
+) -> StdResult<()> {
+  let spot_price = GammV2Querier::new(&deps.querier)
+    .spot_price(pool_id, base_denom.to_string(), quote_denom.to_string())?
+    .spot_price;
+  let twap = TwapQuerier::new(&deps.querier)
+    .arithmetic_twap_to_now(
+      pool_id,
+      base_denom.to_string(),
+      quote_denom.to_string(),
+      Some(OsmosisTimestamp { seconds: start_time.seconds() as i64, nanos: 0 }),
+    )?
+    .arithmetic_twap;
+  let spot_price = Decimal::from_str(&spot_price)?;
+  let twap = Decimal::from_str(&twap)?;
+  let deviation = if spot_price > twap { spot_price - twap } else { twap - spot_price };
+  if deviation > twap * Decimal::from_ratio(max_deviation_bps, BPS_DENOMINATOR) {
+    return Err(StdError::generic_err(format!("Pool {} price deviates from its TWAP", pool_id)));
+  }
+  Ok(())
+}
+
+// every hop of a swap route
+pub fn validate_swap_prices(
+  deps: Deps, routes: &[SwapRoute], denom_in: &str, start_time: Timestamp, max_deviation_bps: u64,
+) -> StdResult<()> {
+  let mut denom_in = denom_in.to_string();
+  for route in routes {
+    validate_spot_price(deps, route.pool_id, &denom_in, &route.token_out_denom, start_time, max_deviation_bps)?;
+    denom_in = route.token_out_denom.clone();
+  }
+  Ok(())
+}
+
+// the price of the joined denom against every other pool asset
+pub fn validate_join_prices(
+  deps: Deps, pool_id: u64, denom_in: &str, start_time: Timestamp, max_deviation_bps: u64,
+) -> StdResult<()> {
+  let liquidity = GammQuerier::new(&deps.querier).total_pool_liquidity(pool_id)?.liquidity;
+  for asset in liquidity.iter().filter(|asset| asset.denom != denom_in) {
+    validate_spot_price(deps, pool_id, denom_in, &asset.denom, start_time, max_deviation_bps)?;
+  }
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use cosmwasm_std::coin;
+
+  #[test]
+  fn take_fee_records_fee_share() {
+    let fee = Some(FeeParams { recipient: "fee_recipient".to_string(), fee_bps: 250 });
+    let mut fees = vec![];
+    assert_eq!(take_fee(Uint128::new(1_000), "uosmo", &fee, &mut fees), Uint128::new(25));
+    assert_eq!(take_fee(Uint128::new(399), "uion", &fee, &mut fees), Uint128::new(9));
+    assert_eq!(take_fee(Uint128::new(200), "uosmo", &fee, &mut fees), Uint128::new(5));
+    assert_eq!(fees, vec![coin(9, "uion"), coin(30, "uosmo")]);
+  }
+
+  #[test]
+  fn take_fee_without_fee_params() {
+    let mut fees = vec![];
+    assert_eq!(take_fee(Uint128::new(1_000), "uosmo", &None, &mut fees), Uint128::zero());
+    // amounts too small for a fee are not recorded
+    let fee = Some(FeeParams { recipient: "fee_recipient".to_string(), fee_bps: 100 });
+    assert_eq!(take_fee(Uint128::new(99), "uosmo", &fee, &mut fees), Uint128::zero());
+    assert!(fees.is_empty());
+  }
+}
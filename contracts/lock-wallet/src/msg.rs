@@ -1,11 +1,19 @@
-use common::types::{RemoveLiquidityParams, SwapParams, AddLiquidityParams};
+use common::types::{RemoveLiquidityParams, RewardParams, FeeParams, SwapParams};
+use common::pending_ops::PendingOp;
 use cosmwasm_schema::{cw_serde, QueryResponses};
 use osmosis_std::types::osmosis::lockup::{LockedResponse};
-use cosmwasm_std::Addr;
+use cosmwasm_std::{Addr, Coin, Timestamp, Uint128};
 
 /// Message type for `instantiate` entry_point
 #[cw_serde]
-pub struct InstantiateMsg {}
+pub struct InstantiateMsg {
+    pub beneficiary: String,
+    pub pool_id: u64,
+    pub duration: u64,
+    pub is_superfluid: bool,
+    // required for superfluid wallets
+    pub validator_address: Option<String>,
+}
 
 /// Message type for `execute` entry_point
 #[cw_serde]
@@ -15,30 +23,48 @@ pub enum ExecuteMsg {
         duration: u64,
         validator_address: Option<String>,
         share_out_min_amount: String,
+        // swap the single fund into a pool asset before joining the pool
+        swap: Option<SwapParams>,
     },
+    // compounded shares are locked with the wallet's own staking mode and validator
     Restake {
-        add_liquidity: AddLiquidityParams,
+        pool_id: u64,
         duration: u64,
-        swap: Option<SwapParams>,
+        rewards: Vec<RewardParams>,
+        // minimum swap output and LP shares are estimated on-chain, minus this slippage
+        max_slippage_bps: u64,
+        // when set, the pools used by the restake must trade within max_slippage_bps of their TWAP over this window
+        twap_window_seconds: Option<u64>,
+        // skimmed from each reward before swapping or joining
+        performance_fee: Option<FeeParams>,
+        // tip for the keeper who triggered the restake, skimmed like the performance fee
+        keeper_fee: Option<FeeParams>,
     },
+    // Unbond, Withdraw and WithdrawAll can be called by the vault or directly by the beneficiary,
+    // funds are always sent to the beneficiary
+    // amount of LP shares to unbond, the whole lock when not set, superfluid locks are always fully unbonded
     Unbond {
         lock_id: u64,
         is_superfluid_staking: bool,
+        amount: Option<String>,
     },
+    // fully unbond every lock the wallet holds on chain, recorded locks keep their own staking mode
+    UnbondAll {},
     Withdraw {
-        receiver: String,
         amount: String,
         denom: String,
     },
     WithdrawAll {
-        receiver: String,
         lp_tokens_out: Option<Vec<RemoveLiquidityParams>>,
     }
 }
 
 /// Message type for `migrate` entry_point
 #[cw_serde]
-pub struct MigrateMsg {}
+pub struct MigrateMsg {
+    // required for wallets created before the config was stored, ignored otherwise
+    pub config: Option<InstantiateMsg>,
+}
 
 /// Message type for `query` entry_point
 #[cw_serde]
@@ -46,7 +72,71 @@ pub struct MigrateMsg {}
 pub enum QueryMsg { 
     #[returns(Addr)]
     GetOwner {},
+    #[returns(ConfigResponse)]
+    GetConfig {},
     #[returns(LockedResponse)]
     Test {},
+    // matured entries are kept until the next unbond of the wallet
+    #[returns(Vec<UnbondingResponse>)]
+    GetUnbonding {},
+    #[returns(Vec<LockResponse>)]
+    GetLocks {},
+    #[returns(FeeStats)]
+    GetFeeStats {},
+    #[returns(RestakeStats)]
+    GetRestakeStats {},
+    // operations still waiting for a reply, entries left after a transaction are stuck
+    #[returns(Vec<PendingOp>)]
+    GetPendingOps {
+        start_after: Option<u64>,
+        limit: u64,
+    },
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub struct RestakeStats {
+    pub last_restake_time: Option<Timestamp>,
+    pub restake_count: u64,
+    // cumulative reward amounts compounded, after fees
+    pub rewards_compounded: Vec<Coin>,
+    pub shares_added: Uint128,
+}
+
+#[cw_serde]
+#[derive(Default)]
+pub struct FeeStats {
+    pub performance_fees: Vec<Coin>,
+    pub keeper_fees: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct ConfigResponse {
+    pub beneficiary: Addr,
+    pub pool_id: u64,
+    pub duration: u64,
+    pub is_superfluid: bool,
+    pub validator_address: Option<String>,
+}
+
+#[cw_serde]
+pub struct UnbondingResponse {
+    pub unbonding_id: u64,
+    // None for partial unbonds, the unlocking coins are no longer under the original lock id
+    pub lock_id: Option<u64>,
+    pub amount: Uint128,
+    pub denom: String,
+    pub is_superfluid: bool,
+    pub end_time: Timestamp,
+}
+
+#[cw_serde]
+pub struct LockResponse {
+    pub lock_id: u64,
+    pub amount: Uint128,
+    pub denom: String,
+    pub duration: u64,
+    pub is_superfluid: bool,
+    pub created_at: Timestamp,
 }
 
@@ -2,7 +2,7 @@ use std::str::FromStr;
 
 use cosmwasm_std::{CosmosMsg, BankMsg, coins, Uint128};
 use osmosis_std::types::osmosis::gamm::v1beta1::{
-  MsgSwapExactAmountIn, SwapAmountInRoute, MsgJoinSwapExternAmountIn, MsgExitSwapShareAmountIn
+  MsgSwapExactAmountIn, SwapAmountInRoute, MsgJoinSwapExternAmountIn, MsgExitSwapShareAmountIn, MsgJoinPool,
 };
 use osmosis_std::types::osmosis::lockup::{
   MsgLockTokens, MsgBeginUnlocking,
@@ -12,6 +12,7 @@ use osmosis_std::types::osmosis::superfluid::{
 };
 use osmosis_std::shim::Duration;
 use osmosis_std::types::cosmos::base::v1beta1::Coin;
+use common::types::SwapRoute;
 
 pub fn get_single_transfer_msg(
   receiver: String, amount: String, denom: String,
@@ -32,16 +33,22 @@ pub fn get_transfer_msg(
   }.into()
 }
 
+pub fn get_swap_routes(routes: Vec<SwapRoute>) -> Vec<SwapAmountInRoute> {
+  routes
+    .into_iter()
+    .map(|route| SwapAmountInRoute {
+      pool_id: route.pool_id,
+      token_out_denom: route.token_out_denom,
+    })
+    .collect()
+}
+
 pub fn get_swap_msg(
-  sender: String, pool_id: u64, amount_in: String, denom_in: String, amount_out_min: String, denom_out: String,
+  sender: String, routes: Vec<SwapRoute>, amount_in: String, denom_in: String, amount_out_min: String,
 ) -> CosmosMsg {
-  let route = SwapAmountInRoute {
-    pool_id,
-    token_out_denom: denom_out,
-  };
   MsgSwapExactAmountIn {
       sender,
-      routes: vec![route],
+      routes: get_swap_routes(routes),
       token_in: Some(Coin { denom: denom_in, amount: amount_in }),
       token_out_min_amount: amount_out_min
   }.into()
@@ -58,6 +65,20 @@ pub fn get_add_liquidity_msg(
   }.into()
 }
 
+pub fn get_join_pool_msg(
+  sender: String, pool_id: u64, share_out_amount: String, token_in_maxs: Vec<cosmwasm_std::Coin>,
+) -> CosmosMsg {
+  MsgJoinPool {
+    sender,
+    pool_id,
+    share_out_amount,
+    token_in_maxs: token_in_maxs
+      .into_iter()
+      .map(|coin| Coin { denom: coin.denom, amount: coin.amount.to_string() })
+      .collect(),
+  }.into()
+}
+
 pub fn get_remove_liquidity_msg(
   owner: String, pool_id: u64, shares: String, denom_out: String, min_tokens: String
 ) -> CosmosMsg {
@@ -83,13 +104,17 @@ pub fn get_lock_tokens_msg(
   }.into()
 }
 
+// empty coins unlock the whole lock
 pub fn get_unlock_msg(
-  owner: String, lock_id: u64,
+  owner: String, lock_id: u64, coins: Vec<cosmwasm_std::Coin>,
 ) -> CosmosMsg {
   MsgBeginUnlocking {
     owner,
     id: lock_id,
-    coins: vec![],
+    coins: coins
+      .into_iter()
+      .map(|coin| Coin { denom: coin.denom, amount: coin.amount.to_string() })
+      .collect(),
   }.into()
 }
 
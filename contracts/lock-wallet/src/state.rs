@@ -1,23 +1,74 @@
 use cosmwasm_schema::{cw_serde};
-use cosmwasm_std::Addr;
-use cw_storage_plus::{Item};
+use cosmwasm_std::{Addr, Coin, Timestamp, Uint128};
+use common::types::SwapParams;
+use common::pending_ops::PendingOps;
+use cw_storage_plus::{Item, Map};
+use crate::msg::{ConfigResponse, FeeStats, RestakeStats};
 
 #[cw_serde]
 pub struct DepositParamsState {
   pub pool_id: u64,
   pub duration: u64,
   pub validator_address: Option<String>,
+  pub funds: Vec<Coin>,
+  pub share_out_min_amount: String,
+  pub swap: Option<SwapParams>,
 }
 
+#[cw_serde]
+pub struct RestakeLegState {
+    pub amount: Uint128,
+    pub denom: String,
+    pub swap: Option<SwapParams>,
+}
+
+// legs are restaked one after another, LP shares are summed up and locked once all legs are done
 #[cw_serde]
 pub struct RestakeParamsState {
     pub pool_id: u64,
     pub duration: u64,
-    pub share_out_min_amount: String,
-    pub swap_denom_out: Option<String>,
+    pub max_slippage_bps: u64,
+    pub twap_window_seconds: Option<u64>,
+    pub pending_legs: Vec<RestakeLegState>,
+    pub current_swap: Option<SwapParams>,
+    pub share_out_amount: Uint128,
+    pub rewards: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct LockParamsState {
+    pub amount: Uint128,
+    pub denom: String,
+    pub duration: u64,
+    pub is_superfluid: bool,
+}
+
+#[cw_serde]
+pub struct LockInfo {
+    pub amount: Uint128,
+    pub denom: String,
+    pub duration: u64,
+    pub is_superfluid: bool,
+    pub created_at: Timestamp,
+}
+
+#[cw_serde]
+pub struct UnbondingInfo {
+    // None for partial unbonds, Osmosis moves the unlocking coins into a lock with a new id
+    pub lock_id: Option<u64>,
+    pub amount: Uint128,
+    pub denom: String,
+    pub is_superfluid: bool,
+    pub end_time: Timestamp,
 }
 
 pub const OWNER: Item<Addr> = Item::new("owner");
-pub const DEPOSIT_PARAMS_REPLY_STATE: Item<DepositParamsState> = Item::new("deposit_params");
-pub const RECEIVER_REPLY_STATE: Item<String> = Item::new("receiver");
-pub const RESTAKE_REPLY_STATE: Item<RestakeParamsState> = Item::new("restake_params");
\ No newline at end of file
+pub const CONFIG: Item<ConfigResponse> = Item::new("config");
+// in-flight DepositParamsState, RestakeParamsState and LockParamsState, keyed by operation id
+pub const PENDING_OPS: PendingOps = PendingOps::new("pending_ops", "last_op_id");
+pub const LOCKS: Map<u64, LockInfo> = Map::new("locks");
+// unbonding id -> unbonding lock, a lock can be partially unbonded more than once
+pub const UNBONDINGS: Map<u64, UnbondingInfo> = Map::new("unbondings");
+pub const LAST_UNBONDING_ID: Item<u64> = Item::new("last_unbonding_id");
+pub const FEE_STATS: Item<FeeStats> = Item::new("fee_stats");
+pub const RESTAKE_STATS: Item<RestakeStats> = Item::new("restake_stats");
\ No newline at end of file
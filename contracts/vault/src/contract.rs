@@ -2,7 +2,7 @@
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
     CosmosMsg, WasmMsg, SubMsg, BankMsg, Addr, Order,
-    Binary, Deps, DepsMut, Env, MessageInfo, Reply,
+    Binary, Deps, DepsMut, Env, MessageInfo, Reply, SubMsgResult,
     Response, StdResult, StdError, to_binary};
 use cw2::{get_contract_version, set_contract_version};
 use semver::Version;
@@ -10,16 +10,29 @@ use semver::Version;
 use crate::error::ContractError;
 use crate::msg::{
     ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, ConfigResponse, RestakeParams, ConfigParams,
+    RestakeFailure, DepositEntry, LockWalletMigration,
+};
+use common::pending_ops::{decode_reply_id, PendingOp};
+use crate::state::{
+    CONFIG, USER_LOCK_WALLET_MAPPING, PENDING_OPS, DepositParamsState,
+    LOCK_WALLET_INFO, LockWalletInfo, LAST_RESTAKE_FAILURES, SWAP_ROUTES,
+    LAST_RESTAKE_TIME, FEE_STATS,
 };
-use crate::state::{CONFIG, USER_LOCK_WALLET_MAPPING, DEPOSIT_PARAMS_REPLY_STATE, DepositParamsState};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:vault";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 const WHITELIST_MAX_LENGTH: u64 = 5;
+const BATCH_DEPOSIT_MAX_LENGTH: u64 = 10;
+const MAX_PERFORMANCE_FEE_BPS: u64 = 2_000;
+const MAX_KEEPER_FEE_BPS: u64 = 1_000;
+// a restake candidates page scans at most limit * factor wallets, each one costs a balance query
+const RESTAKE_CANDIDATES_SCAN_FACTOR: u64 = 5;
 
-const INSTANTIATE_LOCK_WALLET_REPLY_ID: u64 = 1;
+// reply ids carry the operation id of the pending state along with one of these kinds
+const INSTANTIATE_LOCK_WALLET_REPLY_KIND: u64 = 1;
+const RESTAKE_REPLY_KIND: u64 = 2;
 
 /// Handling contract instantiation
 #[cfg_attr(not(feature = "library"), entry_point)]
@@ -38,6 +51,14 @@ pub fn instantiate(
         valid_durations: msg.valid_durations,
         min_deposit_default: msg.min_deposit_default,
         min_deposit_custom: None,
+        max_slippage_bps: msg.max_slippage_bps,
+        performance_fee_bps: 0,
+        fee_recipient: None,
+        keeper_fee_bps: 0,
+        permissionless_restake: false,
+        min_restake_interval_seconds: 0,
+        min_restake_amount: None,
+        twap_window_seconds: 0,
     })?;
     Ok(Response::new()
         .add_attribute("method", "instantiate")
@@ -58,6 +79,19 @@ pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, C
         // TODO: enable it for prod deployment
         // return Err(ContractError::MigrationError { val: "Not a newer version".to_string() });
     }
+    // rewrite the config with the defaults of fields added since it was stored,
+    // max_slippage_bps is 0 until the owner sets it with update_config
+    let config = CONFIG.load(deps.storage)?;
+    CONFIG.save(deps.storage, &config)?;
+    // backfill the wallet reverse index for wallets created before it existed
+    let wallets: Vec<((Addr, (u64, u64)), Addr)> = USER_LOCK_WALLET_MAPPING
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<_>>()?;
+    for ((owner, (pool_id, duration)), wallet_address) in wallets {
+        LOCK_WALLET_INFO.save(deps.storage, wallet_address, &LockWalletInfo { owner, pool_id, duration })?;
+    }
+    // the single slot deposit reply state is replaced by PENDING_OPS
+    deps.storage.remove(b"deposit_params");
     Ok(Response::new())
 }
 
@@ -70,14 +104,20 @@ pub fn execute(
 ) -> Result<Response, ContractError> {
     match msg {
         ExecuteMsg::Deposit {
-            pool_id, duration, share_out_min_amount, is_superfluid_staking
-        } => execute::deposit(deps, env, info, pool_id, duration, share_out_min_amount, is_superfluid_staking),
+            pool_id, duration, share_out_min_amount, is_superfluid_staking, swap
+        } => execute::deposit(deps, env, info, pool_id, duration, share_out_min_amount, is_superfluid_staking, swap),
+        ExecuteMsg::BatchDeposit {
+            deposits,
+        } => execute::batch_deposit(deps, env, info, deposits),
         ExecuteMsg::Restake {
             params,
-        } => execute::restake(deps, info, params),
+        } => execute::restake(deps, env, info, params),
         ExecuteMsg::Unbond {
-            lock_id, pool_id, duration, is_superfluid_staking
-        } => execute::unbond(deps, info, pool_id, duration, lock_id, is_superfluid_staking),
+            lock_id, pool_id, duration, is_superfluid_staking, amount
+        } => execute::unbond(deps, info, pool_id, duration, lock_id, is_superfluid_staking, amount),
+        ExecuteMsg::UnbondAll {
+            pool_id, duration
+        } => execute::unbond_all(deps, info, pool_id, duration),
         ExecuteMsg::Withdraw {
             pool_id, duration, amount, denom
         } => execute::withdraw(deps, info, pool_id, duration, amount, denom),
@@ -89,14 +129,25 @@ pub fn execute(
         } => execute::update_config(deps, info, nconfig),
         ExecuteMsg::RetrieveTokens {
         } => execute::retrieve_tokens(deps, env, info),
+        ExecuteMsg::MigrateLockWallets {
+            wallets
+        } => execute::migrate_lock_wallets(deps, info, wallets),
+        ExecuteMsg::SetSwapRoute {
+            reward_denom, pool_id, routes
+        } => execute::set_swap_route(deps, info, reward_denom, pool_id, routes),
+        ExecuteMsg::RemoveSwapRoute {
+            reward_denom, pool_id
+        } => execute::remove_swap_route(deps, info, reward_denom, pool_id),
     }
 }
 
 pub mod execute {
     use super::*;
-    use cosmwasm_std::Uint128;
+    use std::str::FromStr;
+    use cosmwasm_std::{Coin, Uint128};
     use lock_wallet;
-    use common::types::{RemoveLiquidityParams};
+    use common::types::{RemoveLiquidityParams, RewardParams, SwapParams, SwapRoute, FeeParams, BPS_DENOMINATOR};
+    use common::coins::add_coin;
 
     fn get_lock_wallet(
         deps: &DepsMut, info: &MessageInfo, pool_id: u64, duration: u64
@@ -110,25 +161,35 @@ pub mod execute {
     }
 
     fn create_lock_wallet(
-        deps: DepsMut, env: Env,
-    ) -> Result<Response, ContractError> {
+        deps: DepsMut, env: Env, deposit_params: DepositParamsState,
+    ) -> Result<SubMsg, ContractError> {
         let config = CONFIG.load(deps.storage)?;
         let instantiate_message: CosmosMsg = WasmMsg::Instantiate {
             admin: Some(env.contract.address.to_string()),
             code_id: config.lock_wallet_contract_code_id,
-            msg: to_binary(&lock_wallet::msg::InstantiateMsg {})?,
+            msg: to_binary(&lock_wallet::msg::InstantiateMsg {
+                beneficiary: deposit_params.sender.to_string(),
+                pool_id: deposit_params.pool_id,
+                duration: deposit_params.duration,
+                is_superfluid: deposit_params.is_superfluid_staking,
+                validator_address: if deposit_params.is_superfluid_staking {
+                    Some(config.validator_address.clone())
+                } else {
+                    None
+                },
+            })?,
             funds: vec![],
             label: "lock_wallet".to_string(),
         }.into();
-        Ok(Response::new()
-            .add_attribute("action", "create_lock_wallet")
-            .add_submessage(SubMsg::reply_on_success(instantiate_message, INSTANTIATE_LOCK_WALLET_REPLY_ID))
-        )
+        let reply_id = PENDING_OPS.start(
+            deps.storage, env.block.time, INSTANTIATE_LOCK_WALLET_REPLY_KIND, &deposit_params,
+        )?;
+        Ok(SubMsg::reply_on_success(instantiate_message, reply_id))
     }
 
     pub fn deposit_to_lock_wallet(
         deps: DepsMut, wallet_address: String, deposit_params: DepositParamsState,
-    ) -> Result<Response, ContractError> {
+    ) -> Result<CosmosMsg, ContractError> {
         let config = CONFIG.load(deps.storage)?;
         let validator_address = if deposit_params.is_superfluid_staking {
             Some(config.validator_address)
@@ -141,20 +202,18 @@ pub mod execute {
                 pool_id: deposit_params.pool_id,
                 duration: deposit_params.duration,
                 validator_address,
-                share_out_min_amount: deposit_params.share_out_min_amount })?,
+                share_out_min_amount: deposit_params.share_out_min_amount,
+                swap: deposit_params.swap })?,
             funds: deposit_params.funds
         }.into();
-        Ok(Response::new()
-            .add_attribute("action", "create_lock_wallet")
-            .add_message(deposit_msg)
-        )
+        Ok(deposit_msg)
     }
 
     fn validate_min_deposit_and_duration(
-        deps: &DepsMut, info: &MessageInfo, duration: u64
+        deps: &DepsMut, funds: &[Coin], duration: u64
     ) -> Result<(), ContractError> {
         let config = CONFIG.load(deps.storage)?;
-        let has_invalid_fund = info.funds.clone().into_iter().any(|fund| {
+        let has_invalid_fund = funds.iter().any(|fund| {
             if let Some(min_deposit_custom) = config.min_deposit_custom.clone() {
                 let &min_deposit = min_deposit_custom.get(&fund.denom).unwrap_or(&config.min_deposit_default);
                 fund.amount < Uint128::from(min_deposit)
@@ -171,10 +230,12 @@ pub mod execute {
         Ok(())
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn deposit(
         deps: DepsMut, env: Env, info: MessageInfo, pool_id: u64, duration: u64, share_out_min_amount: String, is_superfluid_staking: bool,
+        swap: Option<SwapParams>,
     ) -> Result<Response, ContractError> {
-        validate_min_deposit_and_duration(&deps, &info, duration)?;
+        validate_min_deposit_and_duration(&deps, &info.funds, duration)?;
         let wallet = USER_LOCK_WALLET_MAPPING
             .may_load(deps.storage, (info.sender.clone(), (pool_id, duration)))?;
         let deposit_params = DepositParamsState {
@@ -184,17 +245,76 @@ pub mod execute {
             share_out_min_amount,
             is_superfluid_staking,
             funds: info.funds,
+            swap,
         };
         if let Some(wallet) = wallet {
-            deposit_to_lock_wallet(deps, wallet.to_string(), deposit_params)
+            Ok(Response::new()
+                .add_attribute("action", "deposit")
+                .add_message(deposit_to_lock_wallet(deps, wallet.to_string(), deposit_params)?)
+            )
         } else {
-            DEPOSIT_PARAMS_REPLY_STATE.save(deps.storage, &deposit_params)?;
-            create_lock_wallet(deps, env)
-        } 
+            Ok(Response::new()
+                .add_attribute("action", "create_lock_wallet")
+                .add_submessage(create_lock_wallet(deps, env, deposit_params)?)
+            )
+        }
+    }
+
+    pub(crate) fn validate_batch_deposit(info: &MessageInfo, deposits: &[DepositEntry]) -> Result<(), ContractError> {
+        if deposits.is_empty() || deposits.len() > BATCH_DEPOSIT_MAX_LENGTH as usize {
+            return Err(ContractError::ValidationError { val: "Invalid number of deposits".to_string() })
+        }
+        let mut targets: Vec<(u64, u64)> = vec![];
+        let mut total: Vec<Coin> = vec![];
+        for deposit in deposits {
+            if targets.contains(&(deposit.pool_id, deposit.duration)) {
+                return Err(ContractError::ValidationError { val: "Duplicate pool and duration".to_string() })
+            }
+            targets.push((deposit.pool_id, deposit.duration));
+            add_coin(&mut total, deposit.amount.clone());
+        }
+        // both sides are sorted by denom without duplicates
+        if total != info.funds {
+            return Err(ContractError::ValidationError { val: "Funds do not match the deposits".to_string() })
+        }
+        Ok(())
+    }
+
+    pub fn batch_deposit(
+        mut deps: DepsMut, env: Env, info: MessageInfo, deposits: Vec<DepositEntry>,
+    ) -> Result<Response, ContractError> {
+        validate_batch_deposit(&info, &deposits)?;
+        let mut response = Response::new().add_attribute("action", "batch_deposit");
+        for deposit in deposits {
+            let funds = vec![deposit.amount];
+            validate_min_deposit_and_duration(&deps, &funds, deposit.duration)?;
+            let wallet = USER_LOCK_WALLET_MAPPING
+                .may_load(deps.storage, (info.sender.clone(), (deposit.pool_id, deposit.duration)))?;
+            let deposit_params = DepositParamsState {
+                sender: info.sender.clone(),
+                pool_id: deposit.pool_id,
+                duration: deposit.duration,
+                share_out_min_amount: deposit.share_out_min_amount,
+                is_superfluid_staking: deposit.is_superfluid_staking,
+                funds,
+                swap: deposit.swap,
+            };
+            if let Some(wallet) = wallet {
+                response = response.add_message(
+                    deposit_to_lock_wallet(deps.branch(), wallet.to_string(), deposit_params)?
+                );
+            } else {
+                response = response.add_submessage(
+                    create_lock_wallet(deps.branch(), env.clone(), deposit_params)?
+                );
+            }
+        }
+        Ok(response)
     }
 
     pub fn unbond(
         deps: DepsMut, info: MessageInfo, pool_id: u64, duration: u64, lock_id: u64, is_superfluid_staking: bool,
+        amount: Option<String>,
     ) -> Result<Response, ContractError> {
         let wallet_address = get_lock_wallet(&deps, &info, pool_id, duration)?;
         let unbond_msg: CosmosMsg = WasmMsg::Execute {
@@ -202,6 +322,7 @@ pub mod execute {
             msg: to_binary(&lock_wallet::msg::ExecuteMsg::Unbond {
                 lock_id,
                 is_superfluid_staking,
+                amount,
             })?,
             funds: vec![],
         }.into();
@@ -211,6 +332,21 @@ pub mod execute {
         )
     }
 
+    pub fn unbond_all(
+        deps: DepsMut, info: MessageInfo, pool_id: u64, duration: u64,
+    ) -> Result<Response, ContractError> {
+        let wallet_address = get_lock_wallet(&deps, &info, pool_id, duration)?;
+        let unbond_msg: CosmosMsg = WasmMsg::Execute {
+            contract_addr: wallet_address.to_string(),
+            msg: to_binary(&lock_wallet::msg::ExecuteMsg::UnbondAll {})?,
+            funds: vec![],
+        }.into();
+        Ok(Response::new()
+            .add_attribute("action", "unbond_all")
+            .add_message(unbond_msg)
+        )
+    }
+
     pub fn withdraw(
         deps: DepsMut, info: MessageInfo, pool_id: u64, duration: u64, amount: String, denom: String,
     ) -> Result<Response, ContractError> {
@@ -218,7 +354,6 @@ pub mod execute {
         let withdraw_msg: CosmosMsg = WasmMsg::Execute {
             contract_addr: wallet_address.to_string(),
             msg: to_binary(&lock_wallet::msg::ExecuteMsg::Withdraw {
-                receiver: info.sender.to_string(),
                 amount,
                 denom,
             })?,
@@ -235,7 +370,6 @@ pub mod execute {
         let withdraw_msg: CosmosMsg = WasmMsg::Execute {
             contract_addr: wallet_address.to_string(),
             msg: to_binary(&lock_wallet::msg::ExecuteMsg::WithdrawAll {
-                receiver: info.sender.to_string(),
                 lp_tokens_out,
             })?,
             funds: vec![],
@@ -281,6 +415,39 @@ pub mod execute {
             if let Some(min_deposit_custom) = nconfig.min_deposit_custom {
                 config.min_deposit_custom = Some(min_deposit_custom);
             }
+            if let Some(max_slippage_bps) = nconfig.max_slippage_bps {
+                if max_slippage_bps > BPS_DENOMINATOR {
+                    return Err(ContractError::ValidationError { val: "Invalid max slippage".to_string() })
+                }
+                config.max_slippage_bps = max_slippage_bps;
+            }
+            if let Some(performance_fee_bps) = nconfig.performance_fee_bps {
+                if performance_fee_bps > MAX_PERFORMANCE_FEE_BPS {
+                    return Err(ContractError::ValidationError { val: "Performance fee is too high".to_string() })
+                }
+                config.performance_fee_bps = performance_fee_bps;
+            }
+            if let Some(fee_recipient) = nconfig.fee_recipient {
+                config.fee_recipient = Some(deps.api.addr_validate(&fee_recipient)?);
+            }
+            if let Some(keeper_fee_bps) = nconfig.keeper_fee_bps {
+                if keeper_fee_bps > MAX_KEEPER_FEE_BPS {
+                    return Err(ContractError::ValidationError { val: "Keeper fee is too high".to_string() })
+                }
+                config.keeper_fee_bps = keeper_fee_bps;
+            }
+            if let Some(permissionless_restake) = nconfig.permissionless_restake {
+                config.permissionless_restake = permissionless_restake;
+            }
+            if let Some(min_restake_interval_seconds) = nconfig.min_restake_interval_seconds {
+                config.min_restake_interval_seconds = min_restake_interval_seconds;
+            }
+            if let Some(min_restake_amount) = nconfig.min_restake_amount {
+                config.min_restake_amount = Some(min_restake_amount);
+            }
+            if let Some(twap_window_seconds) = nconfig.twap_window_seconds {
+                config.twap_window_seconds = twap_window_seconds;
+            }
             if let Some(whitelist) = nconfig.whitelist {
                 if whitelist.len() > WHITELIST_MAX_LENGTH as usize {
                     return Err(ContractError::CustomError { val: "Too many whitelists".to_string() })
@@ -291,29 +458,254 @@ pub mod execute {
                 }).collect();
                 config.whitelist = whitelist_addresses?;
             }
+            // without an interval anyone could restake a wallet every block and collect its keeper fee
+            if config.permissionless_restake && config.min_restake_interval_seconds == 0 {
+                return Err(ContractError::ValidationError {
+                    val: "Permissionless restake requires a min restake interval".to_string()
+                })
+            }
+            // without minimum amounts a restake of dust would hold off the wallet for the whole interval
+            let has_min_restake_amount = config.min_restake_amount.as_ref()
+                .map_or(false, |min_restake_amount| !min_restake_amount.is_empty());
+            if config.permissionless_restake && !has_min_restake_amount {
+                return Err(ContractError::ValidationError {
+                    val: "Permissionless restake requires a min restake amount".to_string()
+                })
+            }
+            // anyone could otherwise move a pool price before restaking at it in the same transaction
+            if config.permissionless_restake && config.twap_window_seconds == 0 {
+                return Err(ContractError::ValidationError {
+                    val: "Permissionless restake requires a TWAP window".to_string()
+                })
+            }
             Ok(config)
         })?;
         Ok(Response::new())
     }
 
+    // only lock wallets created by the vault can be restaked, with their own pool and duration
+    fn validate_restake_target(deps: &DepsMut, item: &RestakeParams) -> Result<Addr, ContractError> {
+        let contract_address = deps.api.addr_validate(&item.contract_address)?;
+        let wallet_info = LOCK_WALLET_INFO.may_load(deps.storage, contract_address.clone())?;
+        if let Some(wallet_info) = wallet_info {
+            if wallet_info.pool_id != item.pool_id || wallet_info.duration != item.duration {
+                return Err(ContractError::ValidationError {
+                    val: format!("Pool or duration mismatch for wallet {}", item.contract_address)
+                })
+            }
+            Ok(contract_address)
+        } else {
+            Err(ContractError::ValidationError {
+                val: format!("Unknown lock wallet {}", item.contract_address)
+            })
+        }
+    }
+
+    pub fn set_swap_route(
+        deps: DepsMut, info: MessageInfo, reward_denom: String, pool_id: u64, routes: Vec<SwapRoute>,
+    ) -> Result<Response, ContractError> {
+        validate_contract_owner(&deps, &info)?;
+        if routes.is_empty() {
+            return Err(ContractError::ValidationError { val: "Swap route is empty".to_string() })
+        }
+        SWAP_ROUTES.save(deps.storage, (reward_denom.clone(), pool_id), &routes)?;
+        Ok(Response::new()
+            .add_attribute("action", "set_swap_route")
+            .add_attribute("reward_denom", reward_denom)
+            .add_attribute("pool_id", pool_id.to_string())
+        )
+    }
+
+    pub fn remove_swap_route(
+        deps: DepsMut, info: MessageInfo, reward_denom: String, pool_id: u64,
+    ) -> Result<Response, ContractError> {
+        validate_contract_owner(&deps, &info)?;
+        SWAP_ROUTES.remove(deps.storage, (reward_denom.clone(), pool_id));
+        Ok(Response::new()
+            .add_attribute("action", "remove_swap_route")
+            .add_attribute("reward_denom", reward_denom)
+            .add_attribute("pool_id", pool_id.to_string())
+        )
+    }
+
+    // rewards without swap params use the registered route to the pool, if any
+    fn fill_swap_routes(deps: &DepsMut, pool_id: u64, rewards: Vec<RewardParams>) -> Result<Vec<RewardParams>, ContractError> {
+        rewards.into_iter().map(|mut reward| -> Result<RewardParams, ContractError> {
+            if reward.swap.is_none() {
+                let routes = SWAP_ROUTES.may_load(deps.storage, (reward.denom.clone(), pool_id))?;
+                reward.swap = routes.map(|routes| SwapParams {
+                    pool_id: None,
+                    denom_out: None,
+                    routes: Some(routes),
+                });
+            }
+            Ok(reward)
+        }).collect()
+    }
+
+    /*
+        In permissionless mode, swaps only follow registered routes and rewards are restaked in balance mode,
+        otherwise a caller could restake dust and hold off the wallet for min_restake_interval_seconds
+    */
+    fn validate_permissionless_restake(item: &RestakeParams) -> Result<(), ContractError> {
+        if item.rewards.iter().any(|reward| reward.swap.is_some()) {
+            return Err(ContractError::ValidationError { val: "Only registered swap routes are allowed".to_string() })
+        }
+        if item.rewards.iter().any(|reward| reward.amount.is_some() || reward.reserve.is_some()) {
+            return Err(ContractError::ValidationError { val: "Only the whole reward balance can be restaked".to_string() })
+        }
+        Ok(())
+    }
+
+    fn is_restaked_recently(
+        deps: &DepsMut, env: &Env, config: &ConfigResponse, contract_address: &Addr,
+    ) -> Result<bool, ContractError> {
+        let last_restake_time = LAST_RESTAKE_TIME.may_load(deps.storage, contract_address.clone())?;
+        if let Some(last_restake_time) = last_restake_time {
+            return Ok(env.block.time < last_restake_time.plus_seconds(config.min_restake_interval_seconds))
+        }
+        Ok(false)
+    }
+
+    // drop the rewards below the min_restake_amount of their denom, the balance mode is checked against the wallet balance
+    fn filter_min_restake_amount(
+        deps: &DepsMut, config: &ConfigResponse, contract_address: &Addr, rewards: Vec<RewardParams>,
+    ) -> Result<Vec<RewardParams>, ContractError> {
+        let min_restake_amount = if let Some(min_restake_amount) = config.min_restake_amount.clone() {
+            min_restake_amount
+        } else {
+            return Ok(rewards)
+        };
+        let mut qualified_rewards: Vec<RewardParams> = vec![];
+        for reward in rewards {
+            let &min_amount = min_restake_amount.get(&reward.denom).unwrap_or(&0);
+            let amount = if let Some(amount) = reward.amount.clone() {
+                Uint128::from_str(&amount)?
+            } else {
+                let balance = deps.querier.query_balance(contract_address, reward.denom.clone())?;
+                let reserve = Uint128::from_str(&reward.reserve.clone().unwrap_or_else(|| "0".to_string()))?;
+                balance.amount.saturating_sub(reserve)
+            };
+            if amount >= Uint128::from(min_amount) {
+                qualified_rewards.push(reward);
+            }
+        }
+        Ok(qualified_rewards)
+    }
+
+    /*
+        Each wallet is restaked in its own submessage with reply_always,
+        so a failing wallet is recorded in LAST_RESTAKE_FAILURES instead of reverting the whole batch
+    */
     pub fn restake(
-        deps: DepsMut, info: MessageInfo, params: Vec<RestakeParams>,
+        deps: DepsMut, env: Env, info: MessageInfo, params: Vec<RestakeParams>,
     ) -> Result<Response, ContractError> {
-        validate_contract_whitelist(&deps, &info)?;
-        let execute_msgs: Result<Vec<CosmosMsg>, _> = params.into_iter().map(|item| -> Result<CosmosMsg, ContractError> {
-            Ok(WasmMsg::Execute {
+        let config = CONFIG.load(deps.storage)?;
+        if !config.permissionless_restake {
+            validate_contract_whitelist(&deps, &info)?;
+        }
+        let performance_fee = match (&config.fee_recipient, config.performance_fee_bps) {
+            (Some(fee_recipient), fee_bps) if fee_bps > 0 => Some(FeeParams {
+                recipient: fee_recipient.to_string(),
+                fee_bps,
+            }),
+            _ => None,
+        };
+        let keeper_fee = if config.keeper_fee_bps > 0 {
+            Some(FeeParams {
+                recipient: info.sender.to_string(),
+                fee_bps: config.keeper_fee_bps,
+            })
+        } else {
+            None
+        };
+        LAST_RESTAKE_FAILURES.save(deps.storage, &Vec::new())?;
+        let mut response = Response::new().add_attribute("action", "restake");
+        let mut sub_msgs: Vec<SubMsg> = vec![];
+        let mut restaked_wallets: Vec<Addr> = vec![];
+        for item in params {
+            let contract_address = validate_restake_target(&deps, &item)?;
+            if item.max_slippage_bps > config.max_slippage_bps {
+                return Err(ContractError::ValidationError { val: "Slippage exceeds the allowed maximum".to_string() })
+            }
+            if restaked_wallets.contains(&contract_address) {
+                return Err(ContractError::ValidationError {
+                    val: format!("Wallet {} is restaked twice", contract_address)
+                })
+            }
+            if config.permissionless_restake {
+                validate_permissionless_restake(&item)?;
+            }
+            restaked_wallets.push(contract_address.clone());
+            if is_restaked_recently(&deps, &env, &config, &contract_address)? {
+                response = response
+                    .add_attribute("restake_skipped", contract_address)
+                    .add_attribute("restake_skip_reason", "Restaked too recently");
+                continue;
+            }
+            let rewards = filter_min_restake_amount(&deps, &config, &contract_address, item.rewards)?;
+            if rewards.is_empty() {
+                response = response
+                    .add_attribute("restake_skipped", contract_address)
+                    .add_attribute("restake_skip_reason", "Rewards below min restake amount");
+                continue;
+            }
+            let reply_id = PENDING_OPS.start(deps.storage, env.block.time, RESTAKE_REPLY_KIND, &contract_address)?;
+            let execute_msg: CosmosMsg = WasmMsg::Execute {
                 contract_addr: item.contract_address,
                 msg: to_binary(&lock_wallet::msg::ExecuteMsg::Restake {
-                    add_liquidity: item.add_liquidity,
+                    pool_id: item.pool_id,
                     duration: item.duration,
-                    swap: item.swap,
+                    rewards: fill_swap_routes(&deps, item.pool_id, rewards)?,
+                    max_slippage_bps: item.max_slippage_bps,
+                    twap_window_seconds: if config.twap_window_seconds > 0 {
+                        Some(config.twap_window_seconds)
+                    } else {
+                        None
+                    },
+                    performance_fee: performance_fee.clone(),
+                    keeper_fee: keeper_fee.clone(),
                 })?,
                 funds: vec![],
-            }.into())
-        }).collect();
+            }.into();
+            sub_msgs.push(SubMsg::reply_always(execute_msg, reply_id));
+        }
+        Ok(response.add_submessages(sub_msgs))
+    }
+
+    pub fn migrate_lock_wallets(
+        deps: DepsMut, info: MessageInfo, wallets: Vec<LockWalletMigration>,
+    ) -> Result<Response, ContractError> {
+        validate_contract_owner(&deps, &info)?;
+        let config = CONFIG.load(deps.storage)?;
+        let mut migrate_msgs: Vec<CosmosMsg> = vec![];
+        for wallet in wallets {
+            let contract_address = deps.api.addr_validate(&wallet.contract_address)?;
+            let wallet_info = LOCK_WALLET_INFO.may_load(deps.storage, contract_address.clone())?
+                .ok_or_else(|| ContractError::ValidationError {
+                    val: format!("Unknown lock wallet {}", wallet.contract_address)
+                })?;
+            migrate_msgs.push(WasmMsg::Migrate {
+                contract_addr: contract_address.to_string(),
+                new_code_id: config.lock_wallet_contract_code_id,
+                msg: to_binary(&lock_wallet::msg::MigrateMsg {
+                    config: Some(lock_wallet::msg::InstantiateMsg {
+                        beneficiary: wallet_info.owner.to_string(),
+                        pool_id: wallet_info.pool_id,
+                        duration: wallet_info.duration,
+                        is_superfluid: wallet.is_superfluid,
+                        validator_address: if wallet.is_superfluid {
+                            Some(config.validator_address.clone())
+                        } else {
+                            None
+                        },
+                    }),
+                })?,
+            }.into());
+        }
         Ok(Response::new()
-            .add_attribute("action", "restake")
-            .add_messages(execute_msgs?)
+            .add_attribute("action", "migrate_lock_wallets")
+            .add_messages(migrate_msgs)
         )
     }
 
@@ -331,19 +723,33 @@ pub mod execute {
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
         QueryMsg::Config {  } => to_binary(&CONFIG.load(deps.storage)?),
         QueryMsg::GetTotalWallets {  } => to_binary(&query::get_total_wallets(deps)?),
+        QueryMsg::GetUserUnbondings { address } => to_binary(&query::get_user_unbondings(deps, address)?),
         QueryMsg::GetLockWalletByAccount { address } => to_binary(&query::get_lock_wallet_by_account(deps, address)?),
         QueryMsg::GetWallets { limit, last_value } => to_binary(&query::get_wallets(deps, limit, last_value)?),
+        QueryMsg::GetLastRestakeFailures {  } => to_binary(&query::get_last_restake_failures(deps)?),
+        QueryMsg::GetSwapRoutes { limit, last_value } => to_binary(&query::get_swap_routes(deps, limit, last_value)?),
+        QueryMsg::GetPendingOps { start_after, limit } => to_binary(&query::get_pending_ops(deps, start_after, limit)?),
+        QueryMsg::GetFeeStats {  } => to_binary(&query::get_fee_stats(deps)?),
+        QueryMsg::GetRestakeCandidates { limit, start_after } => to_binary(
+            &query::get_restake_candidates(deps, env, limit, start_after)?
+        ),
     }
 }
 
 pub mod query {
     use cw_storage_plus::Bound;
 
-    use crate::msg::LockWalletResponse;
+    use cosmwasm_std::{Coin, Uint128};
+    use common::types::LP_DENOM_PREFIX;
+    use lock_wallet::msg::FeeStats;
+
+    use crate::msg::{
+        LockWalletResponse, SwapRouteResponse, RestakeCandidate, RestakeCandidatesResponse, UserUnbondingResponse,
+    };
 
     use super::*;
 
@@ -365,6 +771,32 @@ pub mod query {
         Ok(wallet_addresses)
     }
 
+    // unbonding locks of every wallet of the user, wallets without unbonding locks are left out
+    pub fn get_user_unbondings(deps: Deps, address: String) -> StdResult<Vec<UserUnbondingResponse>> {
+        let account_address = deps.api.addr_validate(&address)?;
+        let wallets: Vec<((u64, u64), Addr)> = USER_LOCK_WALLET_MAPPING
+            .prefix(account_address)
+            .range(deps.storage, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()?;
+        let mut user_unbondings: Vec<UserUnbondingResponse> = vec![];
+        for ((pool_id, duration), wallet_address) in wallets {
+            let unbondings: Vec<lock_wallet::msg::UnbondingResponse> = deps.querier.query_wasm_smart(
+                wallet_address.to_string(),
+                &lock_wallet::msg::QueryMsg::GetUnbonding {},
+            )?;
+            if unbondings.is_empty() {
+                continue;
+            }
+            user_unbondings.push(UserUnbondingResponse {
+                contract_address: wallet_address.to_string(),
+                pool_id,
+                duration,
+                unbondings,
+            });
+        }
+        Ok(user_unbondings)
+    }
+
     pub fn get_total_wallets(deps: Deps) -> StdResult<u64> {
         Ok(USER_LOCK_WALLET_MAPPING
             .range(deps.storage, None, None, Order::Ascending)
@@ -372,6 +804,89 @@ pub mod query {
         )
     }
 
+    pub fn get_last_restake_failures(deps: Deps) -> StdResult<Vec<RestakeFailure>> {
+        Ok(LAST_RESTAKE_FAILURES.may_load(deps.storage)?.unwrap_or_default())
+    }
+
+    pub fn get_pending_ops(deps: Deps, start_after: Option<u64>, limit: u64) -> StdResult<Vec<PendingOp>> {
+        PENDING_OPS.range(deps.storage, start_after, limit)
+    }
+
+    pub fn get_fee_stats(deps: Deps) -> StdResult<FeeStats> {
+        Ok(FEE_STATS.may_load(deps.storage)?.unwrap_or_default())
+    }
+
+    pub fn get_swap_routes(deps: Deps, limit: u64, last_value: Option<(String, u64)>) -> StdResult<Vec<SwapRouteResponse>> {
+        let min_value = last_value.map(Bound::exclusive);
+        SWAP_ROUTES
+            .range(deps.storage, min_value, None, Order::Ascending)
+            .take(limit as usize)
+            .map(|item| {
+                let ((reward_denom, pool_id), routes) = item?;
+                Ok(SwapRouteResponse { reward_denom, pool_id, routes })
+            })
+            .collect()
+    }
+
+    /*
+        Wallets restaked within the interval and wallets without rewards above the min_restake_amount are left out
+        The scan is bounded, next_start_after is set while wallets are left to scan even if the page is not full
+    */
+    pub fn get_restake_candidates(
+        deps: Deps, env: Env, limit: u64, start_after: Option<String>,
+    ) -> StdResult<RestakeCandidatesResponse> {
+        let config = CONFIG.load(deps.storage)?;
+        let min_value = match start_after {
+            Some(address) => Some(Bound::exclusive(deps.api.addr_validate(&address)?)),
+            None => None,
+        };
+        let max_scanned = limit.saturating_mul(RESTAKE_CANDIDATES_SCAN_FACTOR) as usize;
+        let mut candidates: Vec<RestakeCandidate> = vec![];
+        let mut scanned: usize = 0;
+        let mut last_scanned: Option<Addr> = None;
+        for item in LOCK_WALLET_INFO.range(deps.storage, min_value, None, Order::Ascending).take(max_scanned) {
+            if candidates.len() >= limit as usize {
+                break;
+            }
+            let (contract_address, wallet_info) = item?;
+            scanned += 1;
+            last_scanned = Some(contract_address.clone());
+            let last_restake_time = LAST_RESTAKE_TIME.may_load(deps.storage, contract_address.clone())?;
+            if let Some(last_restake_time) = last_restake_time {
+                if env.block.time < last_restake_time.plus_seconds(config.min_restake_interval_seconds) {
+                    continue;
+                }
+            }
+            let rewards: Vec<Coin> = deps.querier.query_all_balances(contract_address.to_string())?
+                .into_iter()
+                .filter(|balance| {
+                    let min_amount = config.min_restake_amount.as_ref()
+                        .and_then(|min_restake_amount| min_restake_amount.get(&balance.denom).copied())
+                        .unwrap_or(0);
+                    !balance.denom.starts_with(LP_DENOM_PREFIX)
+                        && !balance.amount.is_zero()
+                        && balance.amount >= Uint128::from(min_amount)
+                })
+                .collect();
+            if rewards.is_empty() {
+                continue;
+            }
+            candidates.push(RestakeCandidate {
+                contract_address,
+                owner: wallet_info.owner,
+                pool_id: wallet_info.pool_id,
+                duration: wallet_info.duration,
+                last_restake_time,
+                rewards,
+            });
+        }
+        let exhausted = scanned < max_scanned && candidates.len() < limit as usize;
+        Ok(RestakeCandidatesResponse {
+            candidates,
+            next_start_after: if exhausted { None } else { last_scanned.map(|address| address.to_string()) },
+        })
+    }
+
     pub fn get_wallets(deps: Deps, limit: u64, last_value: Option<(String, u64, u64)>) -> StdResult<Vec<LockWalletResponse>> {
         let min_value = last_value.map(|s| {
             let (address, pool_id, duration) = s;
@@ -396,28 +911,141 @@ pub mod query {
 }
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn reply(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
-    match msg.id {
-        INSTANTIATE_LOCK_WALLET_REPLY_ID => reply::handle_instantiate_lock_wallet(deps, msg),
-        id => Err(ContractError::CustomError { val: format!("Unknow reply id: {}", id) } ),
+pub fn reply(deps: DepsMut, env: Env, msg: Reply) -> Result<Response, ContractError> {
+    let (op_id, kind) = decode_reply_id(msg.id);
+    match kind {
+        INSTANTIATE_LOCK_WALLET_REPLY_KIND => reply::handle_instantiate_lock_wallet(deps, op_id, msg),
+        RESTAKE_REPLY_KIND => reply::handle_restake(deps, env, op_id, msg),
+        _ => Err(ContractError::CustomError { val: format!("Unknow reply id: {}", msg.id) } ),
     }
 }
 
 pub mod reply {
     use super::*;
     use cw0::parse_reply_instantiate_data;
+    use cosmwasm_std::{Event, SubMsgResponse};
+    use common::coins::{add_coin, parse_coins};
     
     pub fn handle_instantiate_lock_wallet(
-        deps: DepsMut, msg: Reply,
+        deps: DepsMut, op_id: u64, msg: Reply,
     ) -> Result<Response, ContractError> {
+        let deposit_params: DepositParamsState = PENDING_OPS.load(deps.storage, op_id)?;
+        PENDING_OPS.finish(deps.storage, op_id);
         let res = parse_reply_instantiate_data(msg).map_err(|err| StdError::generic_err(err.to_string()))?;
         let contract_address = deps.api.addr_validate(&res.contract_address)?;
-        let deposit_params = DEPOSIT_PARAMS_REPLY_STATE.load(deps.storage)?;
         USER_LOCK_WALLET_MAPPING.save(
             deps.storage,
             (deposit_params.sender.clone(), (deposit_params.pool_id, deposit_params.duration)),
             &contract_address
         )?;
-        execute::deposit_to_lock_wallet(deps, contract_address.to_string(), deposit_params)
+        LOCK_WALLET_INFO.save(deps.storage, contract_address.clone(), &LockWalletInfo {
+            owner: deposit_params.sender.clone(),
+            pool_id: deposit_params.pool_id,
+            duration: deposit_params.duration,
+        })?;
+        let deposit_msg = execute::deposit_to_lock_wallet(deps, contract_address.to_string(), deposit_params)?;
+        Ok(Response::new()
+            .add_attribute("action", "create_lock_wallet")
+            .add_message(deposit_msg)
+        )
+    }
+
+    // sums the fee attributes emitted by the restaked wallet into the vault totals
+    fn save_fee_stats(deps: DepsMut, contract_address: &Addr, events: &[Event]) -> StdResult<()> {
+        let mut fee_stats = FEE_STATS.may_load(deps.storage)?.unwrap_or_default();
+        let wallet_events = events.iter().filter(|event| {
+            event.ty == "wasm" && event.attributes.iter()
+                .any(|attr| attr.key == "_contract_address" && attr.value == contract_address.as_str())
+        });
+        for event in wallet_events {
+            for attr in event.attributes.iter() {
+                let fees = match attr.key.as_str() {
+                    "performance_fee" => &mut fee_stats.performance_fees,
+                    "keeper_fee" => &mut fee_stats.keeper_fees,
+                    _ => continue,
+                };
+                for coin in parse_coins(&attr.value)? {
+                    add_coin(fees, coin);
+                }
+            }
+        }
+        FEE_STATS.save(deps.storage, &fee_stats)
+    }
+
+    pub fn handle_restake(deps: DepsMut, env: Env, op_id: u64, msg: Reply) -> Result<Response, ContractError> {
+        let contract_address: Addr = PENDING_OPS.load(deps.storage, op_id)?;
+        PENDING_OPS.finish(deps.storage, op_id);
+        match msg.result {
+            SubMsgResult::Ok(SubMsgResponse { events, .. }) => {
+                LAST_RESTAKE_TIME.save(deps.storage, contract_address.clone(), &env.block.time)?;
+                save_fee_stats(deps, &contract_address, &events)?;
+                Ok(Response::new()
+                    .add_attribute("restake_success", contract_address)
+                )
+            },
+            SubMsgResult::Err(error) => {
+                LAST_RESTAKE_FAILURES.update(deps.storage, |mut failures| -> StdResult<_> {
+                    failures.push(RestakeFailure {
+                        contract_address: contract_address.clone(),
+                        error: error.clone(),
+                    });
+                    Ok(failures)
+                })?;
+                Ok(Response::new()
+                    .add_attribute("restake_failure", contract_address)
+                    .add_attribute("restake_error", error)
+                )
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::{coin, Coin};
+    use cosmwasm_std::testing::mock_info;
+
+    fn entry(pool_id: u64, duration: u64, amount: Coin) -> DepositEntry {
+        DepositEntry {
+            pool_id,
+            duration,
+            amount,
+            share_out_min_amount: "1".to_string(),
+            is_superfluid_staking: false,
+            swap: None,
+        }
+    }
+
+    #[test]
+    fn batch_deposit_funds_match_entries() {
+        let deposits = vec![
+            entry(1, 240, coin(100, "uosmo")),
+            entry(2, 240, coin(50, "uosmo")),
+            entry(2, 120, coin(10, "uion")),
+        ];
+        let info = mock_info("user", &[coin(10, "uion"), coin(150, "uosmo")]);
+        assert!(execute::validate_batch_deposit(&info, &deposits).is_ok());
+
+        let info = mock_info("user", &[coin(10, "uion"), coin(149, "uosmo")]);
+        assert!(execute::validate_batch_deposit(&info, &deposits).is_err());
+        let info = mock_info("user", &[coin(150, "uosmo")]);
+        assert!(execute::validate_batch_deposit(&info, &deposits).is_err());
+        let info = mock_info("user", &[coin(10, "uion"), coin(150, "uosmo"), coin(1, "uatom")]);
+        assert!(execute::validate_batch_deposit(&info, &deposits).is_err());
+    }
+
+    #[test]
+    fn batch_deposit_rejects_invalid_entries() {
+        let info = mock_info("user", &[coin(200, "uosmo")]);
+        let duplicate = vec![entry(1, 240, coin(100, "uosmo")), entry(1, 240, coin(100, "uosmo"))];
+        assert!(execute::validate_batch_deposit(&info, &duplicate).is_err());
+        assert!(execute::validate_batch_deposit(&info, &[]).is_err());
+
+        let too_many: Vec<DepositEntry> = (0..=BATCH_DEPOSIT_MAX_LENGTH)
+            .map(|pool_id| entry(pool_id, 240, coin(1, "uosmo")))
+            .collect();
+        let info = mock_info("user", &[coin(too_many.len() as u128, "uosmo")]);
+        assert!(execute::validate_batch_deposit(&info, &too_many).is_err());
     }
 }
@@ -1,8 +1,10 @@
 use std::{collections::HashMap};
 
 use cosmwasm_schema::{cw_serde, QueryResponses};
-use cosmwasm_std::Addr;
-use common::types::{RemoveLiquidityParams, SwapParams, AddLiquidityParams};
+use cosmwasm_std::{Addr, Coin, Timestamp};
+use common::types::{RemoveLiquidityParams, RewardParams, SwapRoute, SwapParams};
+use common::pending_ops::PendingOp;
+use lock_wallet::msg::{UnbondingResponse, FeeStats};
 
 /// Message type for `instantiate` entry_point
 #[cw_serde]
@@ -11,16 +13,37 @@ pub struct InstantiateMsg {
     pub valid_durations: Vec<u64>,
     pub validator_address: String,
     pub lock_wallet_contract_code_id: u64,
+    pub max_slippage_bps: u64,
 }
 
+// the staking mode is owned by the lock wallet, restake can not override it
 #[cw_serde]
 pub struct RestakeParams {
     pub contract_address: String,
-    pub add_liquidity: AddLiquidityParams,
+    pub pool_id: u64,
     pub duration: u64,
+    pub rewards: Vec<RewardParams>,
+    // must not exceed the max_slippage_bps of the config
+    pub max_slippage_bps: u64,
+}
+
+#[cw_serde]
+pub struct DepositEntry {
+    pub pool_id: u64,
+    pub duration: u64,
+    pub amount: Coin,
+    pub share_out_min_amount: String,
+    pub is_superfluid_staking: bool,
     pub swap: Option<SwapParams>,
 }
 
+// staking mode of a wallet created before lock wallets stored their config
+#[cw_serde]
+pub struct LockWalletMigration {
+    pub contract_address: String,
+    pub is_superfluid: bool,
+}
+
 #[cw_serde]
 pub struct ConfigParams {
     pub validator_address: Option<String>,
@@ -29,24 +52,47 @@ pub struct ConfigParams {
     pub valid_durations: Option<Vec<u64>>,
     pub min_deposit_custom: Option<HashMap<String, u64>>,
     pub min_deposit_default: Option<u64>,
+    pub max_slippage_bps: Option<u64>,
+    pub performance_fee_bps: Option<u64>,
+    pub fee_recipient: Option<String>,
+    pub keeper_fee_bps: Option<u64>,
+    pub permissionless_restake: Option<bool>,
+    pub min_restake_interval_seconds: Option<u64>,
+    pub min_restake_amount: Option<HashMap<String, u64>>,
+    pub twap_window_seconds: Option<u64>,
 }
 #[cw_serde]
 pub enum ExecuteMsg {
+    // LP shares of the pool (gamm/pool/N) are locked without joining the pool
+    // multiple coins join the pool with all assets, the coins not taken by the pool are refunded
+    // with swap, a single coin of any denom is swapped into a pool asset first
     Deposit {
         pool_id: u64,
         duration: u64,
         share_out_min_amount: String,
         is_superfluid_staking: bool,
+        swap: Option<SwapParams>,
     },
-    // only owner and whitelist addresses can call restake
+    // the sent funds must be exactly the sum of the entry amounts, one entry per pool and duration
+    BatchDeposit {
+        deposits: Vec<DepositEntry>,
+    },
+    // only owner and whitelist addresses can call restake, anyone when permissionless_restake is on
+    // wallets which don't qualify are skipped and reported in the restake_skipped attributes
     Restake {
         params: Vec<RestakeParams>
     },
+    // amount of LP shares to unbond, the whole lock when not set, only for non superfluid locks
     Unbond {
         lock_id: u64,
         pool_id: u64,
         duration: u64,
         is_superfluid_staking: bool,
+        amount: Option<String>,
+    },
+    UnbondAll {
+        pool_id: u64,
+        duration: u64,
     },
     Withdraw {
         amount: String,
@@ -65,6 +111,21 @@ pub enum ExecuteMsg {
     },
     // only owner can retrieve tokens
     RetrieveTokens {},
+    // only owner can migrate lock wallets to lock_wallet_contract_code_id,
+    // wallets without a config get one from their registered owner, pool and duration
+    MigrateLockWallets {
+        wallets: Vec<LockWalletMigration>,
+    },
+    // only owner can manage swap routes, used by restake for rewards without swap params
+    SetSwapRoute {
+        reward_denom: String,
+        pool_id: u64,
+        routes: Vec<SwapRoute>,
+    },
+    RemoveSwapRoute {
+        reward_denom: String,
+        pool_id: u64,
+    },
 }
 
 /// Message type for `migrate` entry_point
@@ -88,6 +149,32 @@ pub enum QueryMsg {
     },
     #[returns(u64)]
     GetTotalWallets {},
+    #[returns(Vec<UserUnbondingResponse>)]
+    GetUserUnbondings {
+        address: String,
+    },
+    #[returns(Vec<RestakeFailure>)]
+    GetLastRestakeFailures {},
+    #[returns(Vec<SwapRouteResponse>)]
+    GetSwapRoutes {
+        limit: u64,
+        last_value: Option<(String, u64)>,
+    },
+    // operations still waiting for a reply, entries left after a transaction are stuck
+    #[returns(Vec<PendingOp>)]
+    GetPendingOps {
+        start_after: Option<u64>,
+        limit: u64,
+    },
+    // fees taken by all lock wallets restaked through the vault
+    #[returns(FeeStats)]
+    GetFeeStats {},
+    // wallets due for restake with their reward balances, start_after is a lock wallet address
+    #[returns(RestakeCandidatesResponse)]
+    GetRestakeCandidates {
+        limit: u64,
+        start_after: Option<String>,
+    },
 }
 
 #[cw_serde]
@@ -98,6 +185,44 @@ pub struct LockWalletResponse {
     pub duration: u64,
 }
 
+#[cw_serde]
+pub struct UserUnbondingResponse {
+    pub contract_address: String,
+    pub pool_id: u64,
+    pub duration: u64,
+    pub unbondings: Vec<UnbondingResponse>,
+}
+
+#[cw_serde]
+pub struct SwapRouteResponse {
+    pub reward_denom: String,
+    pub pool_id: u64,
+    pub routes: Vec<SwapRoute>,
+}
+
+#[cw_serde]
+pub struct RestakeCandidate {
+    pub contract_address: Addr,
+    pub owner: Addr,
+    pub pool_id: u64,
+    pub duration: u64,
+    pub last_restake_time: Option<Timestamp>,
+    pub rewards: Vec<Coin>,
+}
+
+#[cw_serde]
+pub struct RestakeCandidatesResponse {
+    pub candidates: Vec<RestakeCandidate>,
+    // pass as start_after to continue the scan, None once every wallet was scanned
+    pub next_start_after: Option<String>,
+}
+
+#[cw_serde]
+pub struct RestakeFailure {
+    pub contract_address: Addr,
+    pub error: String,
+}
+
 #[cw_serde]
 pub struct ConfigResponse {
     pub owner: Addr,
@@ -107,4 +232,26 @@ pub struct ConfigResponse {
     pub valid_durations: Vec<u64>,
     pub min_deposit_default: u64,
     pub min_deposit_custom: Option<HashMap<String, u64>>,
+    // fields below default when loading a config stored before they existed
+    #[serde(default)]
+    pub max_slippage_bps: u64,
+    // share of compounded rewards sent to fee_recipient, no fee is taken without a recipient
+    #[serde(default)]
+    pub performance_fee_bps: u64,
+    #[serde(default)]
+    pub fee_recipient: Option<Addr>,
+    // share of compounded rewards paid to the sender of restake
+    #[serde(default)]
+    pub keeper_fee_bps: u64,
+    // anyone can restake, swap routes must come from the registry
+    #[serde(default)]
+    pub permissionless_restake: bool,
+    // wallets restaked within the interval or with rewards below the minimum amount are skipped
+    #[serde(default)]
+    pub min_restake_interval_seconds: u64,
+    #[serde(default)]
+    pub min_restake_amount: Option<HashMap<String, u64>>,
+    // restakes are rejected while a pool price is off its TWAP over this window by more than the slippage, 0 disables it
+    #[serde(default)]
+    pub twap_window_seconds: u64,
 }
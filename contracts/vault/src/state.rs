@@ -1,7 +1,10 @@
 use cosmwasm_schema::cw_serde;
-use cosmwasm_std::{Addr, Coin};
+use cosmwasm_std::{Addr, Coin, Timestamp};
 use cw_storage_plus::{Item, Map};
-use crate::msg::ConfigResponse;
+use common::types::{SwapRoute, SwapParams};
+use common::pending_ops::PendingOps;
+use lock_wallet::msg::FeeStats;
+use crate::msg::{ConfigResponse, RestakeFailure};
 
 #[cw_serde]
 pub struct DepositParamsState {
@@ -11,8 +14,25 @@ pub struct DepositParamsState {
   pub share_out_min_amount: String,
   pub is_superfluid_staking: bool,
   pub funds: Vec<Coin>,
+  pub swap: Option<SwapParams>,
+}
+
+#[cw_serde]
+pub struct LockWalletInfo {
+  pub owner: Addr,
+  pub pool_id: u64,
+  pub duration: u64,
 }
 
 pub const CONFIG: Item<ConfigResponse> = Item::new("config");
 pub const USER_LOCK_WALLET_MAPPING: Map<(Addr, (u64, u64)), Addr> = Map::new("user_lock_wallet_mapping");
-pub const DEPOSIT_PARAMS_REPLY_STATE: Item<DepositParamsState> = Item::new("deposit_params");
\ No newline at end of file
+// reverse index of USER_LOCK_WALLET_MAPPING: wallet address -> (owner, pool_id, duration)
+pub const LOCK_WALLET_INFO: Map<Addr, LockWalletInfo> = Map::new("lock_wallet_info");
+// (reward_denom, target_pool_id) -> swap route registered by the owner
+pub const SWAP_ROUTES: Map<(String, u64), Vec<SwapRoute>> = Map::new("swap_routes");
+// in-flight DepositParamsState of lock wallet instantiations and lock wallet Addr of restakes, keyed by operation id
+pub const PENDING_OPS: PendingOps = PendingOps::new("pending_ops", "last_op_id");
+pub const LAST_RESTAKE_TIME: Map<Addr, Timestamp> = Map::new("last_restake_time");
+pub const LAST_RESTAKE_FAILURES: Item<Vec<RestakeFailure>> = Item::new("last_restake_failures");
+// fees taken by all lock wallets restaked through the vault
+pub const FEE_STATS: Item<FeeStats> = Item::new("fee_stats");